@@ -7,9 +7,9 @@ fn unclamp(val: usize, min: usize, max: usize) -> Option<usize> {
     assert!(min < max);
     // assert!(val != min && val != max, "{:?} {:?} {:?}", val, min, max);
     if min < val && val < max {
-        return Some(val);
+        Some(val)
     } else {
-        return None;
+        None
     }
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,23 +23,18 @@ pub enum Roundness {
 pub fn round_date(date: impl chrono::Datelike, roundness: Roundness) -> chrono::NaiveDate {
     let date = chrono::NaiveDate::from_yo_opt(date.year(), date.ordinal()).unwrap();
     match roundness {
-        Roundness::None => return date,
-        Roundness::Day => {
-            return date;
-        }
+        Roundness::None => date,
+        Roundness::Day => date,
         Roundness::Week => {
             let point = date;
             let fake_weekstart = (point.day0() / 7) * 7 + 1; // fake because it's not on monday
-            let point =
-                chrono::NaiveDate::from_ymd_opt(point.year(), point.month(), fake_weekstart)
-                    .unwrap();
-            return point;
+            chrono::NaiveDate::from_ymd_opt(point.year(), point.month(), fake_weekstart).unwrap()
         }
         Roundness::Month => {
             let point = date;
-            return chrono::NaiveDate::from_ymd_opt(point.year(), point.month(), 1).unwrap();
+            chrono::NaiveDate::from_ymd_opt(point.year(), point.month(), 1).unwrap()
         }
-    };
+    }
 }
 
 fn select_midpoint_rounded(
@@ -57,30 +52,21 @@ fn select_midpoint_rounded(
     let naive_midpoint = (good_old_pos + bad_new_pos) / 2;
     let naive_dt = tags[naive_midpoint].tag_datetime();
     let round_before = round_date(naive_dt.date(), roundness);
-    let round_after;
-    match roundness {
+    let round_after = match roundness {
         Roundness::None => return Some(naive_midpoint),
-        Roundness::Day => {
-            round_after = round_before + chrono::Duration::days(1);
-        }
-        Roundness::Week => {
-            round_after = round_before + chrono::Duration::days(7);
-        }
-        Roundness::Month => {
-            round_after = round_before + chrono::Months::new(1);
-        }
+        Roundness::Day => round_before + chrono::Duration::days(1),
+        Roundness::Week => round_before + chrono::Duration::days(7),
+        Roundness::Month => round_before + chrono::Months::new(1),
     };
     // log::debug!("ndt rb ra = {:?} {:?} {:?}", naive_dt, round_before, round_after);
     let day_first = tags
         .iter()
         .enumerate()
-        .filter(|(_, r)| r.tag_datetime().date() >= round_before)
-        .last();
+        .rfind(|(_, r)| r.tag_datetime().date() >= round_before);
     let next_day_first = tags
         .iter()
         .enumerate()
-        .filter(|(_, r)| r.tag_datetime().date() >= round_after)
-        .last();
+        .rfind(|(_, r)| r.tag_datetime().date() >= round_after);
     let (Some(day_first), Some(next_day_first)) = (day_first, next_day_first) else {
         return None;
     };
@@ -107,9 +93,9 @@ fn select_midpoint_rounded(
     let diff_after = naive_midpoint.abs_diff(next_day_first_p);
     // log::debug!("diff before {} , after {}", diff_before, diff_after);
     if diff_before < diff_after {
-        return Some(day_first_p);
+        Some(day_first_p)
     } else {
-        return Some(next_day_first_p);
+        Some(next_day_first_p)
     }
 }
 
@@ -162,10 +148,14 @@ pub fn select_midpoint_tag<'a>(
     // let midpoint = (bad_pos + good_pos) / 2;
 
     let midpoint = select_midpoint(&releases.tags_list, good_pos, bad_pos);
-    return &releases.tags_list[midpoint];
+    &releases.tags_list[midpoint]
 }
 
-pub fn get_steps_left(releases: &ReleaseHub, latest_good_tag: &GitTag, earliest_bad_tag: &GitTag) -> i32 {
+pub fn get_steps_left(
+    releases: &ReleaseHub,
+    latest_good_tag: &GitTag,
+    earliest_bad_tag: &GitTag,
+) -> i32 {
     let lg = releases
         .tags_list
         .iter()
@@ -179,8 +169,7 @@ pub fn get_steps_left(releases: &ReleaseHub, latest_good_tag: &GitTag, earliest_
         .find(|r| r.1.name == earliest_bad_tag.name)
         .unwrap();
     let span = eb.0.abs_diff(lg.0);
-    let steps = (span as f32).log2().ceil() as i32;
-    return steps;
+    (span as f32).log2().ceil() as i32
 }
 
 // pub fn select_next_tag_to_try<'a>(releases: &'a ReleaseHub, track: &crate::Track) -> &str{
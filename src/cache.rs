@@ -0,0 +1,354 @@
+//! Disk usage of downloaded archives and unpacked builds, and cleaning them up.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// What `cache gc` is allowed to remove
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct CachePolicy {
+    /// stop removing things once archives and unpacks take up no more than this
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// this many most recently used builds are never removed
+    #[serde(default)]
+    pub keep_recent: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+struct UsageEntry {
+    tag: String,
+    last_used: chrono::DateTime<chrono::Local>,
+}
+
+/// Which tag each asset belongs to and when it was last activated, by asset file name
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct Usage(BTreeMap<String, UsageEntry>);
+
+fn usage_file() -> PathBuf {
+    Path::new("cache/usage.json").into()
+}
+
+impl Usage {
+    pub fn load() -> anyhow::Result<Self> {
+        let file = usage_file();
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(file)?)?)
+    }
+    fn save(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(usage_file().parent().unwrap())?;
+        std::fs::File::create(usage_file())?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+    pub fn touch(tag: &str, asset_name: &str) -> anyhow::Result<()> {
+        let mut usage = Self::load()?;
+        usage.0.insert(
+            asset_name.to_string(),
+            UsageEntry {
+                tag: tag.to_string(),
+                last_used: chrono::Local::now(),
+            },
+        );
+        usage.save()
+    }
+}
+
+pub fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+    let mut total = 0;
+    for file in crate::fsutil::list_files(dir)? {
+        total += std::fs::metadata(dir.join(file))?.len();
+    }
+    Ok(total)
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if value < 1024.0 {
+            return format!("{:.1} {}", value, unit);
+        }
+        value /= 1024.0;
+    }
+    format!("{:.1} TiB", value)
+}
+
+/// A downloaded archive and/or its unpacked build
+#[derive(Debug)]
+pub struct CachedBuild {
+    /// archive file name without extensions, also the name of the unpack dir
+    pub stem: String,
+    /// unknown for builds activated before usage was tracked
+    pub tag: Option<String>,
+    pub archive: Option<(PathBuf, u64)>,
+    /// with the bytes only this build has, which is what removing it frees
+    pub unpacked: Option<(PathBuf, u64)>,
+    /// falls back to the files' modification time
+    pub last_used: chrono::DateTime<chrono::Local>,
+    /// size of each file of the unpacked build, by `file_key`
+    files: BTreeMap<String, u64>,
+}
+pub fn stem(name: &str) -> String {
+    name.split('.').next().unwrap().to_string()
+}
+
+fn modified(path: &Path) -> Option<chrono::DateTime<chrono::Local>> {
+    Some(std::fs::metadata(path).ok()?.modified().ok()?.into())
+}
+
+/// Sizes of the files of an unpacked build. Files linked from the store are keyed by their object, so that
+/// the builds sharing one count it once between them.
+fn unpacked_files(build_dir: &Path) -> anyhow::Result<BTreeMap<String, u64>> {
+    let manifest = crate::store::read_manifest(build_dir)?.unwrap_or_default();
+    let mut out = BTreeMap::new();
+    for rel in crate::fsutil::list_files(build_dir)? {
+        let path = build_dir.join(&rel);
+        let key = match manifest.get(&rel) {
+            Some(hash) => format!("store:{}", hash),
+            None => path.to_string_lossy().to_string(),
+        };
+        out.insert(key, std::fs::metadata(&path)?.len());
+    }
+    Ok(out)
+}
+
+/// How many of `builds` have each file
+fn file_counts(builds: &[CachedBuild]) -> BTreeMap<&str, usize> {
+    let mut counts = BTreeMap::new();
+    for key in builds.iter().flat_map(|b| b.files.keys()) {
+        *counts.entry(key.as_str()).or_default() += 1;
+    }
+    counts
+}
+
+/// What `builds` take up on disk, counting files shared through the store once
+pub fn total_size(builds: &[CachedBuild]) -> u64 {
+    let files = builds
+        .iter()
+        .flat_map(|b| &b.files)
+        .collect::<BTreeMap<_, _>>();
+    let archives = builds.iter().map(|b| b.archive.as_ref().map_or(0, |a| a.1));
+    archives.sum::<u64>() + files.into_values().sum::<u64>()
+}
+
+/// Files and dirs directly in `dir`, by stem. Leftovers of interrupted unpacks are left out.
+fn children(dir: &Path, dirs: bool) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    let mut out = BTreeMap::new();
+    if !dir.exists() {
+        return Ok(out);
+    }
+    for e in std::fs::read_dir(dir)? {
+        let path = e?.path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if path.is_dir() == dirs && !name.starts_with("_unpack") {
+            out.insert(stem(&name), path);
+        }
+    }
+    Ok(out)
+}
+
+/// Everything in `distr_dir` and `unpack_dir`, least recently used first
+pub fn scan(distr_dir: &Path, unpack_dir: &Path) -> anyhow::Result<Vec<CachedBuild>> {
+    let usage = Usage::load()?;
+    let archives = children(distr_dir, false)?;
+    let unpacks = children(unpack_dir, true)?;
+    let stems = archives
+        .keys()
+        .chain(unpacks.keys())
+        .collect::<BTreeSet<_>>();
+    let mut out = vec![];
+    for s in stems {
+        let used = usage
+            .0
+            .iter()
+            .find(|(name, _)| stem(name) == *s)
+            .map(|u| u.1);
+        let archive = match archives.get(s) {
+            Some(path) => Some((path.clone(), std::fs::metadata(path)?.len())),
+            None => None,
+        };
+        let files = match unpacks.get(s) {
+            Some(path) => unpacked_files(path)?,
+            None => BTreeMap::new(),
+        };
+        let unpacked = unpacks.get(s).map(|path| (path.clone(), 0));
+        let last_used = used
+            .map(|u| u.last_used)
+            .or_else(|| {
+                let paths = archive.iter().chain(unpacked.iter());
+                paths.filter_map(|(p, _)| modified(p)).max()
+            })
+            .unwrap_or_default();
+        out.push(CachedBuild {
+            stem: s.clone(),
+            tag: used.map(|u| u.tag.clone()),
+            archive,
+            unpacked,
+            last_used,
+            files,
+        });
+    }
+    let counts = file_counts(&out)
+        .into_iter()
+        .map(|(k, n)| (k.to_string(), n))
+        .collect::<BTreeMap<_, _>>();
+    for build in &mut out {
+        let own = build.files.iter().filter(|(k, _)| counts[k.as_str()] == 1);
+        let own = own.map(|(_, size)| size).sum();
+        if let Some(unpacked) = &mut build.unpacked {
+            unpacked.1 = own;
+        }
+    }
+    out.sort_by_key(|b| b.last_used);
+    Ok(out)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Removal {
+    Unpacked(PathBuf, u64),
+    Archive(PathBuf, u64),
+}
+
+/// What to remove to satisfy `policy`. Builds whose stem is in `protected` are kept.
+/// Unpacks that can be recreated from their archive go first, then whole builds, oldest first.
+/// Files shared through the store are only counted as freed once every build that has them is gone.
+pub fn plan_gc(
+    builds: &[CachedBuild],
+    policy: &CachePolicy,
+    protected: &BTreeSet<String>,
+) -> Vec<Removal> {
+    let recent = builds
+        .iter()
+        .rev()
+        .take(policy.keep_recent)
+        .map(|b| b.stem.as_str())
+        .collect::<BTreeSet<_>>();
+    let removable = builds
+        .iter()
+        .filter(|b| !recent.contains(b.stem.as_str()) && !protected.contains(&b.stem))
+        .collect::<Vec<_>>();
+    let mut total = total_size(builds);
+    let done = |total: u64| policy.max_bytes.is_some_and(|max| total <= max);
+    let mut counts = file_counts(builds);
+    // the bytes removing the unpacked build frees, given what's been removed before it
+    let mut remove_files = |build: &CachedBuild| {
+        let mut freed = 0;
+        for (key, size) in &build.files {
+            let count = counts.get_mut(key.as_str()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                freed += size;
+            }
+        }
+        freed
+    };
+
+    let mut out = vec![];
+    for build in &removable {
+        if let (Some(_), Some((path, _))) = (&build.archive, &build.unpacked) {
+            if done(total) {
+                return out;
+            }
+            let freed = remove_files(build);
+            out.push(Removal::Unpacked(path.clone(), freed));
+            total -= freed;
+        }
+    }
+    for build in &removable {
+        if done(total) {
+            return out;
+        }
+        if let (None, Some((path, _))) = (&build.archive, &build.unpacked) {
+            let freed = remove_files(build);
+            out.push(Removal::Unpacked(path.clone(), freed));
+            total -= freed;
+        }
+        if let Some((path, size)) = &build.archive {
+            out.push(Removal::Archive(path.clone(), *size));
+            total -= size;
+        }
+    }
+    out
+}
+
+pub fn execute(removals: &[Removal]) -> anyhow::Result<()> {
+    for removal in removals {
+        match removal {
+            Removal::Unpacked(path, _) => std::fs::remove_dir_all(path)?,
+            Removal::Archive(path, _) => std::fs::remove_file(path)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds are listed least recently used first, like `scan` has them
+    fn cached(stem: &str, archive: bool, files: &[(&str, u64)]) -> CachedBuild {
+        CachedBuild {
+            stem: stem.into(),
+            tag: None,
+            archive: archive.then(|| (PathBuf::from(format!("{}.zip", stem)), 100)),
+            unpacked: (!files.is_empty()).then(|| (PathBuf::from(stem), 0)),
+            last_used: chrono::Local::now(),
+            files: files.iter().map(|&(k, s)| (k.to_string(), s)).collect(),
+        }
+    }
+
+    #[test]
+    fn gc_order_and_what_is_kept() {
+        let builds = vec![
+            cached("oldest", true, &[("oldest/x", 50)]),
+            cached("archive-only", true, &[]),
+            cached(
+                "unpacked-only",
+                false,
+                &[("shared", 40), ("unpacked-only/y", 30)],
+            ),
+            cached("bound", true, &[("bound/z", 20)]),
+            cached("sharing", true, &[("shared", 40)]),
+            cached("newest", true, &[("newest/w", 20)]),
+        ];
+        let policy = |max_bytes| CachePolicy {
+            max_bytes,
+            keep_recent: 1,
+        };
+        let protected = BTreeSet::from(["bound".to_string()]);
+        let unpacked = |stem: &str, freed| Removal::Unpacked(stem.into(), freed);
+        let archive = |stem: &str| Removal::Archive(format!("{}.zip", stem).into(), 100);
+
+        // without a limit everything goes but the protected and the most recent one:
+        // unpacks with an archive to recreate them from first, then whole builds, oldest first.
+        // The shared file only counts as freed with the last build that has it
+        assert_eq!(
+            plan_gc(&builds, &policy(None), &protected),
+            vec![
+                unpacked("oldest", 50),
+                unpacked("sharing", 0),
+                archive("oldest"),
+                archive("archive-only"),
+                unpacked("unpacked-only", 70),
+                archive("sharing"),
+            ]
+        );
+        // 500 in archives and 160 in files, stopping as soon as it fits
+        assert_eq!(total_size(&builds), 660);
+        assert_eq!(
+            plan_gc(&builds, &policy(Some(610)), &protected),
+            vec![unpacked("oldest", 50)]
+        );
+        assert_eq!(
+            plan_gc(&builds, &policy(Some(510)), &protected),
+            vec![
+                unpacked("oldest", 50),
+                unpacked("sharing", 0),
+                archive("oldest"),
+            ]
+        );
+        assert!(plan_gc(&builds, &policy(Some(660)), &protected).is_empty());
+    }
+}
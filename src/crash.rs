@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::SystemTime;
+
+const EXCERPT_LINES: usize = 20;
+
+pub fn describe_exit(status: &ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("signal {}", signal);
+        }
+    }
+    match status.code() {
+        // windows reports crashes as NTSTATUS codes, which are only readable in hex
+        Some(code) if !(-255..=255).contains(&code) => format!("exit code {:#x}", code),
+        Some(code) => format!("exit code {}", code),
+        None => "unknown exit status".into(),
+    }
+}
+
+fn modified_since(path: &Path, since: SystemTime) -> bool {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|m| m >= since)
+        .unwrap_or(false)
+}
+
+fn find_files_named(dir: &Path, name: &str, depth: usize, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                find_files_named(&path, name, depth - 1, out);
+            }
+        } else if entry.file_name().to_string_lossy() == name {
+            out.push(path);
+        }
+    }
+}
+
+fn debug_log(userdata_dir: &Path) -> PathBuf {
+    userdata_dir.join("config").join("debug.log")
+}
+
+/// How long `debug.log` is before a launch. Only what comes after it is from that launch
+pub fn debug_log_len(userdata_dir: &Path) -> u64 {
+    std::fs::metadata(debug_log(userdata_dir))
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+/// `debug.log` past `start`, or all of it if the game started the file over
+fn read_debug_log(path: &Path, start: u64) -> String {
+    let bytes = std::fs::read(path).unwrap_or_default();
+    let start = if (start as usize) <= bytes.len() {
+        start as usize
+    } else {
+        0
+    };
+    String::from_utf8_lossy(&bytes[start..]).to_string()
+}
+
+/// `crash.log`s and `debug.log` written since `since`
+pub fn fresh_logs(userdata_dir: &Path, since: SystemTime) -> Vec<PathBuf> {
+    let mut logs = vec![];
+    find_files_named(userdata_dir, "crash.log", 2, &mut logs);
+    logs.push(debug_log(userdata_dir));
+    logs.retain(|l| modified_since(l, since));
+    logs
+}
+
+/// Excerpts of `crash.log`s written since `since`, and of the errors `debug.log` got past `debug_log_start`
+pub fn collect_evidence(
+    userdata_dir: &Path,
+    since: SystemTime,
+    debug_log_start: u64,
+) -> Vec<(PathBuf, Vec<String>)> {
+    let mut out = vec![];
+    for log in fresh_logs(userdata_dir, since) {
+        let is_debug_log = log.file_name().unwrap() == "debug.log";
+        let text = if is_debug_log {
+            read_debug_log(&log, debug_log_start)
+        } else {
+            std::fs::read_to_string(&log).unwrap_or_default()
+        };
+        let mut lines = text.lines().map(|l| l.to_string()).collect::<Vec<_>>();
+        if is_debug_log {
+            lines.retain(|l| l.contains("ERROR"));
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        let tail = lines[lines.len().saturating_sub(EXCERPT_LINES)..].to_vec();
+        out.push((log, tail));
+    }
+    out
+}
@@ -0,0 +1,362 @@
+//! Differences between the `data/` trees of two builds, per file and per json object.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::fsutil::TreeChange;
+
+/// Identifies a json object across builds, regardless of which file it lives in
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectKey {
+    /// `core` for `data/json`, the mod's directory name for `data/mods`
+    pub source: String,
+    pub object_type: String,
+    pub id: String,
+}
+impl std::fmt::Display for ObjectKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {} {}", self.source, self.object_type, self.id)
+    }
+}
+
+#[derive(Debug)]
+pub struct FieldChange {
+    /// dot-separated, like the data oracle's `path`
+    pub path: String,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default)]
+pub struct DataDiff {
+    pub files: Vec<TreeChange>,
+    /// json files that differ only in whitespace, key order and such
+    pub reformatted: usize,
+    pub added: Vec<ObjectKey>,
+    pub removed: Vec<ObjectKey>,
+    pub changed: Vec<(ObjectKey, Vec<FieldChange>)>,
+}
+
+fn object_id(obj: &serde_json::Value) -> Option<String> {
+    let as_id = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(ids) => Some(
+            ids.iter()
+                .filter_map(|i| i.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        _ => None,
+    };
+    if let Some(id) = obj.get("id").and_then(as_id) {
+        return Some(id);
+    }
+    if let Some(id) = obj.get("abstract").and_then(as_id) {
+        return Some(id);
+    }
+    // recipes are keyed by their result, and the suffix if there are several for one item
+    let result = obj.get("result").and_then(as_id)?;
+    match obj.get("id_suffix").and_then(|s| s.as_str()) {
+        Some(suffix) => Some(format!("{}_{}", result, suffix)),
+        None => Some(result),
+    }
+}
+
+fn parse_json(path: &Path) -> Option<serde_json::Value> {
+    let parsed = serde_json::from_slice(&std::fs::read(path).ok()?);
+    if parsed.is_err() {
+        log::debug!("can't parse {:?}", path);
+    }
+    parsed.ok()
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "json")
+}
+
+/// All identifiable objects under `data_dir`. Later duplicates win, like they mostly do in game.
+fn load_objects(
+    data_dir: &Path,
+    skip: &[PathBuf],
+) -> anyhow::Result<BTreeMap<ObjectKey, serde_json::Value>> {
+    let mut out = BTreeMap::new();
+    for file in crate::fsutil::list_files(data_dir)? {
+        if !is_json(&file) || skip.iter().any(|s| file.starts_with(s)) {
+            continue;
+        }
+        let mut parts = file.iter().map(|p| p.to_string_lossy());
+        let source = match (parts.next().as_deref(), parts.next()) {
+            (Some("json"), _) => "core".to_string(),
+            (Some("mods"), Some(m)) => m.to_string(),
+            _ => continue,
+        };
+        let Some(parsed) = parse_json(&data_dir.join(&file)) else {
+            continue;
+        };
+        let objects = match parsed {
+            serde_json::Value::Array(items) => items,
+            other => vec![other],
+        };
+        for obj in objects {
+            let (Some(object_type), Some(id)) =
+                (obj.get("type").and_then(|t| t.as_str()), object_id(&obj))
+            else {
+                continue;
+            };
+            let key = ObjectKey {
+                source: source.clone(),
+                object_type: object_type.to_string(),
+                id,
+            };
+            out.insert(key, obj);
+        }
+    }
+    Ok(out)
+}
+
+/// Arrays are compared as a whole, element positions rarely mean anything across versions
+fn diff_values(
+    path: &str,
+    old: Option<&serde_json::Value>,
+    new: Option<&serde_json::Value>,
+    out: &mut Vec<FieldChange>,
+) {
+    if old == new {
+        return;
+    }
+    if let (Some(serde_json::Value::Object(old)), Some(serde_json::Value::Object(new))) = (old, new)
+    {
+        let keys = old
+            .keys()
+            .chain(new.keys())
+            .collect::<std::collections::BTreeSet<_>>();
+        for key in keys {
+            let sub = match path {
+                "" => key.to_string(),
+                _ => format!("{}.{}", path, key),
+            };
+            diff_values(&sub, old.get(key), new.get(key), out);
+        }
+        return;
+    }
+    out.push(FieldChange {
+        path: path.to_string(),
+        old: old.cloned(),
+        new: new.cloned(),
+    });
+}
+
+/// Compares `<old>/data` with `<new>/data`, ignoring whatever overlays were copied into them.
+pub fn diff(old_build: &Path, new_build: &Path) -> anyhow::Result<DataDiff> {
+    let (old, new) = (old_build.join("data"), new_build.join("data"));
+    let skip = crate::overlays::installed(old_build)?
+        .into_iter()
+        .chain(crate::overlays::installed(new_build)?)
+        .filter_map(|p| p.strip_prefix("data").ok().map(|p| p.to_path_buf()))
+        .collect::<Vec<_>>();
+
+    let mut out = DataDiff::default();
+    for change in crate::fsutil::diff_trees(&old, &new)? {
+        let (TreeChange::Added(f) | TreeChange::Removed(f) | TreeChange::Changed(f)) = &change;
+        if skip.iter().any(|s| f.starts_with(s)) {
+            continue;
+        }
+        if let TreeChange::Changed(f) = &change {
+            if is_json(f) {
+                let old_parsed = parse_json(&old.join(f));
+                if old_parsed.is_some() && old_parsed == parse_json(&new.join(f)) {
+                    out.reformatted += 1;
+                    continue;
+                }
+            }
+        }
+        out.files.push(change);
+    }
+
+    let old_objects = load_objects(&old, &skip)?;
+    let new_objects = load_objects(&new, &skip)?;
+    for (key, old_obj) in &old_objects {
+        match new_objects.get(key) {
+            None => out.removed.push(key.clone()),
+            Some(new_obj) => {
+                let mut fields = vec![];
+                diff_values("", Some(old_obj), Some(new_obj), &mut fields);
+                if !fields.is_empty() {
+                    out.changed.push((key.clone(), fields));
+                }
+            }
+        }
+    }
+    out.added = new_objects
+        .keys()
+        .filter(|k| !old_objects.contains_key(*k))
+        .cloned()
+        .collect();
+    Ok(out)
+}
+
+fn short(value: &Option<serde_json::Value>) -> String {
+    const MAX: usize = 80;
+    let Some(value) = value else {
+        return "(none)".into();
+    };
+    let text = value.to_string();
+    match text.char_indices().nth(MAX) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text,
+    }
+}
+
+impl DataDiff {
+    pub fn render(&self, markdown: bool) -> anyhow::Result<String> {
+        let mut out = String::new();
+        let (heading, bullet, code) = match markdown {
+            true => ("### ", "- ", "`"),
+            false => ("", "  ", ""),
+        };
+
+        writeln!(out, "{}Files", heading)?;
+        if markdown {
+            writeln!(out)?;
+        }
+        for change in &self.files {
+            let (mark, f) = match change {
+                TreeChange::Added(f) => ("+", f),
+                TreeChange::Removed(f) => ("-", f),
+                TreeChange::Changed(f) => ("~", f),
+            };
+            writeln!(out, "{}{} {}{}{}", bullet, mark, code, f.display(), code)?;
+        }
+        if self.files.is_empty() {
+            writeln!(out, "{}(no changes)", bullet)?;
+        }
+        if self.reformatted > 0 {
+            writeln!(
+                out,
+                "{}({} more json files only changed formatting)",
+                bullet, self.reformatted
+            )?;
+        }
+
+        for (title, keys) in [
+            ("Added objects", &self.added),
+            ("Removed objects", &self.removed),
+        ] {
+            if keys.is_empty() {
+                continue;
+            }
+            writeln!(out)?;
+            writeln!(out, "{}{}", heading, title)?;
+            if markdown {
+                writeln!(out)?;
+            }
+            for key in keys {
+                writeln!(out, "{}{}{}{}", bullet, code, key, code)?;
+            }
+        }
+
+        if !self.changed.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "{}Changed objects", heading)?;
+            if markdown {
+                writeln!(out)?;
+            }
+            for (key, fields) in &self.changed {
+                writeln!(out, "{}{}{}{}", bullet, code, key, code)?;
+                for field in fields {
+                    writeln!(
+                        out,
+                        "  {}{}{}{}: {}{}{} -> {}{}{}",
+                        bullet,
+                        code,
+                        field.path,
+                        code,
+                        code,
+                        short(&field.old),
+                        code,
+                        code,
+                        short(&field.new),
+                        code
+                    )?;
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A build with the given files under `data/`
+    fn build(root: &Path, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = root.join(name);
+        for (path, text) in files {
+            let file = dir.join("data").join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn moved_and_reformatted_objects_are_no_change() {
+        let root = std::env::temp_dir().join(format!("datadiff_{}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        let old = build(
+            &root,
+            "old",
+            &[
+                (
+                    "json/items/tools.json",
+                    r#"[{"type": "TOOL", "id": "hammer", "weight": 10}, {"type": "TOOL", "id": "saw", "weight": 5}]"#,
+                ),
+                (
+                    "json/items/misc.json",
+                    r#"[{"type": "GENERIC", "id": "rock", "weight": 1}]"#,
+                ),
+                (
+                    "mods/Magic/spells.json",
+                    r#"[{"type": "SPELL", "id": "fireball", "damage": 10}]"#,
+                ),
+            ],
+        );
+        let new = build(
+            &root,
+            "new",
+            &[
+                // the saw moved out, and the file got reformatted with its keys reordered
+                (
+                    "json/items/tools.json",
+                    "[\n  { \"id\": \"hammer\", \"weight\": 10, \"type\": \"TOOL\" }\n]\n",
+                ),
+                (
+                    "json/items/saws.json",
+                    r#"[{"type": "TOOL", "id": "saw", "weight": 5}]"#,
+                ),
+                (
+                    "json/items/misc.json",
+                    "[ { \"type\": \"GENERIC\", \"id\": \"rock\", \"weight\": 1 } ]",
+                ),
+                (
+                    "mods/Magic/spells.json",
+                    r#"[{"type": "SPELL", "id": "fireball", "damage": 12}]"#,
+                ),
+            ],
+        );
+
+        let diff = diff(&old, &new).unwrap();
+        assert!(diff.added.is_empty(), "{:?}", diff.added);
+        assert!(diff.removed.is_empty(), "{:?}", diff.removed);
+        assert_eq!(diff.reformatted, 1, "misc.json only changed in whitespace");
+        let [(key, fields)] = diff.changed.as_slice() else {
+            panic!("only the fireball changed: {:?}", diff.changed);
+        };
+        assert_eq!(key.to_string(), "[Magic] SPELL fireball");
+        assert_eq!(fields[0].path, "damage");
+        assert_eq!(fields[0].new, Some(serde_json::json!(12)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,369 @@
+//! Whole bisection sessions, driven through the same commands as the prompt, against stand-ins for everything
+//! outside: the test server for api.github.com and the downloads, a local git repo for `git ls-remote`,
+//! a shell script for 7-zip and another for the game.
+
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::release_hub::CDDA_REPO;
+use crate::{execute, BisectState, TEST_CWD};
+
+/// 2025-01-01 to 2025-01-14, one a day
+fn tags() -> Vec<String> {
+    (1..=14)
+        .map(|day| format!("cdda-experimental-2025-01-{:02}-0000", day))
+        .collect()
+}
+
+fn asset_name(tag: &str) -> String {
+    format!(
+        "cdda-windows-tiles-x64-{}.zip",
+        tag.strip_prefix("cdda-experimental-").unwrap()
+    )
+}
+
+fn write_script(path: &Path, text: &str) {
+    std::fs::write(path, text).unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn run(cmd: &mut Command) {
+    let status = cmd.status().unwrap();
+    assert!(status.success(), "{:?}", cmd);
+}
+
+/// Everything a session needs, in a fresh dir. Versions from `first_bad` on have the bug.
+struct World {
+    dir: PathBuf,
+    server: crate::test_server::TestServer,
+}
+
+impl World {
+    fn new(name: &str, first_bad: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("e2e_{}_{}", name, std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+
+        // the "archives" are tarballs under the zip names, which the stub 7-zip unpacks with tar
+        write_script(
+            &bin.join("7z"),
+            "#!/bin/sh\n\
+             # stands in for `7z x -aou -bb0 -o<dir> <archive>`\n\
+             for arg; do case \"$arg\" in -o*) out=\"${arg#-o}\";; esac; archive=\"$arg\"; done\n\
+             mkdir -p \"$out\" && tar -xf \"$archive\" -C \"$out\"\n",
+        );
+
+        let repo = dir.join("repo.git");
+        run(Command::new("git").args(["init", "-q"]).arg(&repo));
+        let git = || {
+            let mut cmd = Command::new("git");
+            cmd.arg("-C").arg(&repo).args([
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+            ]);
+            cmd
+        };
+
+        let mut assets = HashMap::new();
+        for tag in tags() {
+            run(git().args(["commit", "-q", "--allow-empty", "-m", &tag]));
+            run(git().args(["tag", &tag]));
+
+            let build = dir.join("builds").join(&tag);
+            std::fs::create_dir_all(&build).unwrap();
+            std::fs::write(build.join("VERSION"), &tag).unwrap();
+            if tag.as_str() >= first_bad {
+                std::fs::write(build.join("BUGGY"), "").unwrap();
+            }
+            // called with `--basepath <build> --userdir <dir>`, from the session's dir
+            write_script(
+                &build.join("cataclysm-tiles"),
+                "#!/bin/sh\n\
+                 if [ -e \"$2/BUGGY\" ]; then verdict=bug; else verdict=ok; fi\n\
+                 echo \"$(cat \"$2/VERSION\") $verdict\" >> launches.txt\n",
+            );
+            let archive = dir.join("builds").join(asset_name(&tag));
+            run(Command::new("tar")
+                .arg("-cf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(&build)
+                .arg("."));
+            assets.insert(tag.clone(), std::fs::read(archive).unwrap());
+        }
+
+        let tags = tags();
+        let last_good = tags[tags.iter().position(|t| t == first_bad).unwrap() - 1].clone();
+        let server = crate::test_server::serve_with(|url| {
+            let mut routes = HashMap::new();
+            for (tag, archive) in assets {
+                let download = format!("/download/{}/{}", tag, asset_name(&tag));
+                let release = serde_json::json!({
+                    "id": 1000,
+                    "published_at": "2025-01-01T00:00:00Z",
+                    "tag_name": tag,
+                    "assets": [{"name": asset_name(&tag), "browser_download_url": format!("{}{}", url, download)}],
+                    "html_url": format!("https://github.com/{}/releases/tag/{}", CDDA_REPO, tag),
+                    "target_commitish": "master",
+                });
+                routes.insert(
+                    format!("/repos/{}/releases/tags/{}", CDDA_REPO, tag),
+                    (200, serde_json::to_vec(&release).unwrap()),
+                );
+                routes.insert(download, (200, archive));
+            }
+            let compare = serde_json::json!({
+                "total_commits": 2,
+                "commits": [
+                    {"commit": {"message": "Merge pull request #4242 from someone/branch\n\nBreak the thing"}},
+                    {"commit": {"message": "Unrelated cleanup (#4243)"}},
+                ],
+            });
+            routes.insert(
+                format!("/repos/{}/compare/{}...{}", CDDA_REPO, last_good, first_bad),
+                (200, serde_json::to_vec(&compare).unwrap()),
+            );
+            routes
+        });
+
+        let config = serde_json::json!({
+            "distr_dir": "distr",
+            "unpack_dir": "unpacked",
+            "userdata_dir": "userdata",
+            "zip_extractor_path": bin.join("7z"),
+            "game_executable": "cataclysm-tiles",
+            "release_sources": [
+                {"kind": "git", "url": repo},
+                {"kind": "github", "api_url": server.url, "web_url": server.url},
+            ],
+        });
+        std::fs::write(dir.join("config.json5"), config.to_string()).unwrap();
+        World { dir, server }
+    }
+
+    /// What the stub game said on its last launch
+    fn last_launch(&self) -> (String, bool) {
+        let log = std::fs::read_to_string(self.dir.join("launches.txt")).unwrap();
+        let (tag, verdict) = log.lines().last().unwrap().split_once(' ').unwrap();
+        (tag.to_string(), verdict == "bug")
+    }
+}
+
+fn active_tag(state: &BisectState) -> Option<String> {
+    state
+        .active_install
+        .as_ref()
+        .map(|(r, _)| r.tag_name.clone())
+}
+
+/// Plays the user once: `next`, `run`, and mark as the game behaved.
+/// Returns the tag tried, or `None` once `next` has nothing new to offer.
+fn step(world: &World, state: &mut BisectState) -> Option<String> {
+    let before = active_tag(state);
+    assert!(execute(state, "next").unwrap());
+    if active_tag(state) == before {
+        return None;
+    }
+    assert!(execute(state, "run").unwrap());
+    let (tag, buggy) = world.last_launch();
+    assert_eq!(Some(&tag), active_tag(state).as_ref());
+    let verdict = if buggy { "mark bad" } else { "mark good" };
+    assert!(execute(state, verdict).unwrap());
+    Some(tag)
+}
+
+/// Steps until done. Returns the tags tried, in order.
+fn bisect(world: &World, state: &mut BisectState) -> Vec<String> {
+    let mut tried = vec![];
+    for _ in 0..20 {
+        match step(world, state) {
+            Some(tag) => tried.push(tag),
+            None => return tried,
+        }
+    }
+    panic!("no end to the bisection, tried {:?}", tried);
+}
+
+/// Runs `session` with the world's dir as the current dir, as the sessions work relative to it like the real thing
+fn in_world(world: &World, session: impl FnOnce(&mut BisectState)) {
+    let _cwd = TEST_CWD.lock().unwrap_or_else(|e| e.into_inner());
+    let old = std::env::current_dir().unwrap();
+    std::env::set_current_dir(&world.dir).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut state = BisectState::new().unwrap();
+        session(&mut state);
+    }));
+    std::env::set_current_dir(old).unwrap();
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+#[test]
+fn bisects_to_the_first_bad_version() {
+    let first_bad = "cdda-experimental-2025-01-10-0000";
+    let world = World::new("basic", first_bad);
+    in_world(&world, |state| {
+        let tried = bisect(&world, state);
+        // the newest one, then a week earlier (rounded to the month), then halving
+        assert_eq!(tried[0], "cdda-experimental-2025-01-14-0000");
+        assert_eq!(tried[1], "cdda-experimental-2025-01-01-0000");
+        assert!(tried.len() <= 6, "{:?}", tried);
+        assert_eq!(
+            state.track.bounds(&state.releases.tags_list),
+            (
+                Some("cdda-experimental-2025-01-09-0000".to_string()),
+                Some(first_bad.to_string())
+            )
+        );
+
+        assert!(execute(state, "report report.md").unwrap());
+        let report = std::fs::read_to_string("report.md").unwrap();
+        assert!(report.contains(first_bad), "{}", report);
+        assert!(report.contains("#4242"), "{}", report);
+        assert!(report.contains("Break the thing"), "{}", report);
+    });
+    // everything came through the stand-ins
+    let requests = world.server.requests.lock().unwrap();
+    assert!(requests.iter().any(|r| r.starts_with("GET /download/")));
+    assert!(requests.iter().any(|r| r.contains("/compare/")));
+    std::fs::remove_dir_all(&world.dir).unwrap();
+}
+
+#[test]
+fn picks_up_where_the_last_session_left_off() {
+    let first_bad = "cdda-experimental-2025-01-05-0000";
+    let world = World::new("restart", first_bad);
+    in_world(&world, |state| {
+        assert_eq!(
+            step(&world, state).as_deref(),
+            Some("cdda-experimental-2025-01-14-0000")
+        );
+        assert_eq!(
+            step(&world, state).as_deref(),
+            Some("cdda-experimental-2025-01-01-0000")
+        );
+        assert!(execute(state, "quit").is_ok_and(|more| !more));
+    });
+    in_world(&world, |state| {
+        // the last marked version is active again
+        assert_eq!(
+            active_tag(state).as_deref(),
+            Some("cdda-experimental-2025-01-01-0000")
+        );
+        let tried = bisect(&world, state);
+        assert!(!tried.is_empty());
+        assert_eq!(
+            state.track.bounds(&state.releases.tags_list),
+            (
+                Some("cdda-experimental-2025-01-04-0000".to_string()),
+                Some(first_bad.to_string())
+            )
+        );
+    });
+    // every version was downloaded once, sessions share the cache
+    let requests = world.server.requests.lock().unwrap();
+    let downloads = requests
+        .iter()
+        .filter(|r| r.starts_with("GET /download/"))
+        .collect::<Vec<_>>();
+    let unique = downloads.iter().collect::<std::collections::BTreeSet<_>>();
+    assert_eq!(downloads.len(), unique.len());
+    std::fs::remove_dir_all(&world.dir).unwrap();
+}
+
+#[test]
+fn reset_starts_over() {
+    let first_bad = "cdda-experimental-2025-01-07-0000";
+    let world = World::new("reset", first_bad);
+    let report_rows = |file: &str| {
+        let report = std::fs::read_to_string(file).unwrap();
+        assert!(report.contains(first_bad), "{}", report);
+        assert!(report.contains("#4242"), "{}", report);
+        report
+            .lines()
+            .filter(|l| l.starts_with("| ") && l.as_bytes()[2].is_ascii_digit())
+            .count()
+    };
+    in_world(&world, |state| {
+        assert!(execute(state, "reset").unwrap());
+        let first = bisect(&world, state);
+        assert!(execute(state, "report first.md").unwrap());
+        assert_eq!(report_rows("first.md"), first.len());
+
+        assert!(execute(state, "reset").unwrap());
+        assert!(state.track.0.is_empty());
+        // from the newest version again, as if nothing was marked
+        let second = bisect(&world, state);
+        assert_eq!(first, second);
+        assert!(execute(state, "report second.md").unwrap());
+        assert_eq!(report_rows("second.md"), second.len());
+        assert!(execute(state, "reset").unwrap());
+    });
+    in_world(&world, |state| {
+        assert!(state.track.0.is_empty());
+        assert!(execute(state, "report").is_err());
+    });
+    std::fs::remove_dir_all(&world.dir).unwrap();
+}
+
+#[test]
+fn mirror_serves_only_cached_releases() {
+    use std::io::{BufRead, Write};
+
+    let dir = std::env::temp_dir().join(format!("e2e_mirror_{}", std::process::id()));
+    let tag = "cdda-experimental-2025-01-01-0000";
+    let release = serde_json::json!({
+        "id": 1,
+        "published_at": "2025-01-01T00:00:00Z",
+        "tag_name": tag,
+        "assets": [],
+        "html_url": "",
+        "target_commitish": "",
+    });
+    std::fs::create_dir_all(dir.join("cache/release_info")).unwrap();
+    std::fs::create_dir_all(dir.join("distr")).unwrap();
+    for file in [
+        format!("cache/release_info/{}.json", tag),
+        "secret.json".into(),
+    ] {
+        std::fs::write(dir.join(file), release.to_string()).unwrap();
+    }
+
+    let _cwd = TEST_CWD.lock().unwrap_or_else(|e| e.into_inner());
+    let old = std::env::current_dir().unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+    let result = std::panic::catch_unwind(|| {
+        let addr = crate::mirror::serve("127.0.0.1:0", Path::new("distr"), Vec::new).unwrap();
+        // sent as is, without the client tidying up the path
+        let status = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\n\r\n", path).unwrap();
+            let mut line = String::new();
+            std::io::BufReader::new(stream)
+                .read_line(&mut line)
+                .unwrap();
+            line.trim().to_string()
+        };
+        assert_eq!(
+            status(&format!("/releases/{}.json", tag)),
+            "HTTP/1.1 200 OK"
+        );
+        assert_eq!(
+            status("/releases/../../secret.json"),
+            "HTTP/1.1 404 Not Found"
+        );
+    });
+    std::env::set_current_dir(old).unwrap();
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// A userdata incompatibility workaround, applied before launching any version
+/// whose tag date falls into `[from, until)`.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct FixupRule {
+    pub name: String,
+    #[serde(default)]
+    pub from: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    pub until: Option<chrono::NaiveDate>,
+    /// relative to the userdata dir, e.g. `config/fonts.json`
+    pub path: PathBuf,
+    pub action: FixupAction,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum FixupAction {
+    Remove,
+    Rename {
+        to: PathBuf,
+    },
+    /// move the file aside for versions in range, and put it back for the others
+    Backup,
+    /// plain text substitution, e.g. for renamed option values or tileset ids
+    Replace {
+        from: String,
+        to: String,
+    },
+}
+
+/// The `fix-font` workaround, as a rule: versions from before fonts.json took per-font options can't read
+/// what newer ones wrote, so it's moved aside for them (the game writes its defaults) and put back for newer ones.
+/// Being off on the month only costs the font settings for a few versions, nothing is deleted.
+/// Other files are left alone unless the config says otherwise.
+pub fn builtin_rules() -> Vec<FixupRule> {
+    vec![FixupRule {
+        name: "fonts.json per-font options".into(),
+        from: None,
+        until: chrono::NaiveDate::from_ymd_opt(2024, 6, 1),
+        path: "config/fonts.json".into(),
+        action: FixupAction::Backup,
+    }]
+}
+
+impl FixupRule {
+    pub fn applies_to(&self, date: chrono::NaiveDate) -> bool {
+        self.from.is_none_or(|f| f <= date) && self.until.is_none_or(|u| date < u)
+    }
+
+    /// Inside the userdir, so that isolated userdirs don't trade files
+    fn backup_path(&self, userdata_dir: &Path) -> PathBuf {
+        userdata_dir
+            .join(".fixup_backups")
+            .join(self.name.replace(['/', '\\', ' '], "_"))
+            .join(&self.path)
+    }
+
+    /// Returns whether anything was changed.
+    pub fn apply(&self, userdata_dir: &Path, date: chrono::NaiveDate) -> anyhow::Result<bool> {
+        let file = userdata_dir.join(&self.path);
+        if !self.applies_to(date) {
+            // put back what was moved aside. Whatever an older version wrote in its place goes
+            match &self.action {
+                FixupAction::Backup => {
+                    let backup = self.backup_path(userdata_dir);
+                    if backup.exists() {
+                        std::fs::rename(&backup, &file)?;
+                        return Ok(true);
+                    }
+                }
+                FixupAction::Rename { to } => {
+                    let renamed = userdata_dir.join(to);
+                    if renamed.exists() && !file.exists() {
+                        std::fs::rename(&renamed, &file)?;
+                        return Ok(true);
+                    }
+                }
+                FixupAction::Remove | FixupAction::Replace { .. } => {}
+            }
+            return Ok(false);
+        }
+        if !file.exists() {
+            return Ok(false);
+        }
+        match &self.action {
+            FixupAction::Remove => std::fs::remove_file(&file)?,
+            FixupAction::Rename { to } => std::fs::rename(&file, userdata_dir.join(to))?,
+            FixupAction::Backup => {
+                let backup = self.backup_path(userdata_dir);
+                if backup.exists() {
+                    // the original is already safe, this is what an older version wrote
+                    std::fs::remove_file(&file)?;
+                } else {
+                    std::fs::create_dir_all(backup.parent().unwrap())?;
+                    std::fs::rename(&file, &backup)?;
+                }
+            }
+            FixupAction::Replace { from, to } => {
+                let text = std::fs::read_to_string(&file)?;
+                if !text.contains(from.as_str()) {
+                    return Ok(false);
+                }
+                std::fs::write(&file, text.replace(from.as_str(), to))?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub fn apply_all(
+    rules: &[FixupRule],
+    userdata_dir: &Path,
+    date: chrono::NaiveDate,
+) -> anyhow::Result<()> {
+    for rule in rules {
+        let changed = rule
+            .apply(userdata_dir, date)
+            .with_context(|| format!("applying fixup {:?}", rule.name))?;
+        if changed {
+            log::info!("Applied userdata fixup {:?}", rule.name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,349 @@
+//! Releases of a fork hosted on Gitea or Forgejo, through their `/api/v1` API.
+//! The JSON is close to GitHub's, but mapped explicitly so the differences (nullable dates, drafts) don't leak.
+
+use std::path::PathBuf;
+
+use crate::release_hub::{GitTag, GithubCompare, GithubRelease, ReleaseAsset};
+use crate::release_source::{AssetStream, ReleaseSource};
+
+/// Releases are listed in pages of this many
+const PAGE_SIZE: usize = 50;
+
+#[derive(serde::Deserialize, Debug)]
+struct ForgejoRelease {
+    id: i64,
+    tag_name: String,
+    #[serde(default)]
+    target_commitish: String,
+    #[serde(default)]
+    html_url: String,
+    #[serde(default)]
+    draft: bool,
+    created_at: String,
+    /// unset for drafts
+    #[serde(default)]
+    published_at: Option<String>,
+    #[serde(default)]
+    assets: Vec<ForgejoAsset>,
+}
+#[derive(serde::Deserialize, Debug)]
+struct ForgejoAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl From<ForgejoRelease> for GithubRelease {
+    fn from(r: ForgejoRelease) -> Self {
+        GithubRelease {
+            id: r.id,
+            published_at: r.published_at.unwrap_or(r.created_at),
+            tag_name: r.tag_name,
+            assets: r
+                .assets
+                .into_iter()
+                .map(|a| ReleaseAsset {
+                    name: a.name,
+                    browser_download_url: a.browser_download_url,
+                })
+                .collect(),
+            html_url: r.html_url,
+            target_commitish: r.target_commitish,
+            local: false,
+        }
+    }
+}
+
+pub struct Forgejo {
+    /// e.g. `https://codeberg.org`
+    url: String,
+    /// `owner/name`
+    repo: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Forgejo {
+    pub fn new(url: &str, repo: &str, token: Option<String>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            repo: repo.to_string(),
+            token,
+            agent: ureq::builder().user_agent("moxian-bisector-thingy").build(),
+        }
+    }
+
+    fn get(&self, url: &str) -> ureq::Request {
+        let request = self.agent.get(url);
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("token {}", token)),
+            None => request,
+        }
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}/api/v1/repos/{}/{}", self.url, self.repo, path)
+    }
+}
+
+impl ReleaseSource for Forgejo {
+    fn describe(&self) -> String {
+        format!("{}/{}", self.url, self.repo)
+    }
+    /// The tags of the published releases: plain tags have nothing to download
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+        let mut out = vec![];
+        for page in 1.. {
+            let body = self
+                .get(&self.api("releases"))
+                .query("page", &page.to_string())
+                .query("limit", &PAGE_SIZE.to_string())
+                .call()?
+                .into_string()?;
+            let releases: Vec<ForgejoRelease> = serde_json::from_str(&body)?;
+            let last_page = releases.len() < PAGE_SIZE;
+            for release in releases.into_iter().filter(|r| !r.draft) {
+                let mut tag = GitTag::named(release.tag_name);
+                // forks needn't name their tags like upstream, the release date places those
+                if tag.try_tag_datetime().is_none() {
+                    let date = release.published_at.unwrap_or(release.created_at);
+                    tag.datetime = chrono::DateTime::parse_from_rfc3339(&date)
+                        .ok()
+                        .map(|d| d.naive_utc());
+                }
+                out.push(tag);
+            }
+            if last_page {
+                break;
+            }
+        }
+        Ok(Some(out))
+    }
+    fn lists_all_tags(&self) -> bool {
+        true
+    }
+    fn release_cache_dir(&self) -> Option<PathBuf> {
+        // the fork's releases may well be named like upstream's
+        let host = self
+            .url
+            .split_once("://")
+            .map_or(self.url.as_str(), |x| x.1);
+        let name = format!("{}/{}", host, self.repo)
+            .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_");
+        Some(
+            crate::mirror::release_cache_dir()
+                .join("forgejo")
+                .join(name),
+        )
+    }
+    fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+        let response = match self
+            .get(&self.api(&format!("releases/tags/{}", tag)))
+            .call()
+        {
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            r => r?,
+        };
+        let release: ForgejoRelease = serde_json::from_str(&response.into_string()?)?;
+        Ok(Some(release.into()))
+    }
+    fn open_asset(&self, asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+        // the token is only for our own instance, and not for `<our host>.example.com`
+        if !asset
+            .browser_download_url
+            .starts_with(&format!("{}/", self.url))
+        {
+            return Ok(None);
+        }
+        let response = self.get(&asset.browser_download_url).call()?;
+        Ok(Some(Box::new(response.into_reader())))
+    }
+    fn repo_url(&self) -> Option<String> {
+        Some(format!("{}/{}", self.url, self.repo))
+    }
+    fn pull_url(&self, number: u32) -> Option<String> {
+        Some(format!("{}/{}/pulls/{}", self.url, self.repo, number))
+    }
+    /// The same shape as GitHub's, as far as the report looks
+    fn compare(&self, base: &str, head: &str) -> anyhow::Result<Option<GithubCompare>> {
+        let body = self
+            .get(&self.api(&format!("compare/{}...{}", base, head)))
+            .call()?
+            .into_string()?;
+        Ok(Some(serde_json::from_str(&body)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github_web::{GithubWeb, GITHUB_WEB};
+    use crate::release_hub::CDDA_REPO;
+    use crate::release_source::Sources;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    const REPO: &str = "ourfork/Cataclysm-DDA";
+
+    fn mock() -> crate::test_server::TestServer {
+        let asset = |url: &str, tag: &str, uuid: &str| {
+            serde_json::json!({
+                "id": 7,
+                "name": format!("cdda-windows-tiles-x64-{}.zip", tag),
+                "size": 5,
+                "download_count": 0,
+                "created_at": "2025-03-05T03:10:00+02:00",
+                "uuid": uuid,
+                "browser_download_url": format!("{}/attachments/{}", url, uuid),
+            })
+        };
+        let release = |url: &str, id: i64, tag: &str, published_at: Option<&str>| {
+            serde_json::json!({
+                "id": id,
+                "tag_name": tag,
+                "target_commitish": "main",
+                "name": tag,
+                "body": "",
+                "url": format!("{}/api/v1/repos/{}/releases/{}", url, REPO, id),
+                "html_url": format!("{}/{}/releases/tag/{}", url, REPO, tag),
+                "tarball_url": "",
+                "zipball_url": "",
+                "draft": published_at.is_none(),
+                "prerelease": false,
+                "created_at": "2025-03-05T03:10:00+02:00",
+                "published_at": published_at,
+                "assets": [asset(url, tag, &format!("uuid-{}", id))],
+            })
+        };
+        crate::test_server::serve_with(|url| {
+            let releases = serde_json::json!([
+                // a draft has no published_at, and isn't for everyone's eyes yet
+                release(url, 43, "fork-nightly-118", None),
+                release(
+                    url,
+                    42,
+                    "fork-nightly-117",
+                    Some("2025-03-05T03:10:00+02:00")
+                ),
+                release(
+                    url,
+                    41,
+                    "cdda-experimental-2025-03-02-0012",
+                    Some("2025-03-02T00:31:00Z"),
+                ),
+            ]);
+            HashMap::from([
+                (
+                    format!("/api/v1/repos/{}/releases", REPO),
+                    (200, serde_json::to_vec(&releases).unwrap()),
+                ),
+                (
+                    format!("/api/v1/repos/{}/releases/tags/fork-nightly-117", REPO),
+                    (200, serde_json::to_vec(&releases[1]).unwrap()),
+                ),
+                ("/attachments/uuid-42".to_string(), (200, b"build".to_vec())),
+                (
+                    format!(
+                        "/api/v1/repos/{}/compare/fork-nightly-116...fork-nightly-117",
+                        REPO
+                    ),
+                    (
+                        200,
+                        serde_json::to_vec(&serde_json::json!({
+                            "total_commits": 1,
+                            "commits": [{
+                                "sha": "0123456789abcdef0123456789abcdef01234567",
+                                "commit": {"message": "Faster monsters (#12)"},
+                            }],
+                        }))
+                        .unwrap(),
+                    ),
+                ),
+            ])
+        })
+    }
+
+    #[test]
+    fn lists_tags_and_maps_releases() {
+        let server = mock();
+        let forgejo = Forgejo::new(&server.url, REPO, Some("secret".into()));
+
+        let tags = forgejo.tags().unwrap().unwrap();
+        let names = tags.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["fork-nightly-117", "cdda-experimental-2025-03-02-0012"]
+        );
+        assert_eq!(
+            tags[0].tag_datetime(),
+            chrono::NaiveDate::from_ymd_opt(2025, 3, 5)
+                .unwrap()
+                .and_hms_opt(1, 10, 0)
+                .unwrap()
+        );
+        assert_eq!(tags[1].datetime, None);
+        assert!(server.requests.lock().unwrap()[0].contains("/releases"));
+
+        let release = forgejo.release("fork-nightly-117").unwrap().unwrap();
+        assert_eq!(release.id, 42);
+        assert_eq!(release.published_at, "2025-03-05T03:10:00+02:00");
+        assert_eq!(release.assets.len(), 1);
+        assert!(forgejo.release("no-such-tag").unwrap().is_none());
+
+        let mut body = vec![];
+        forgejo
+            .open_asset(&release.assets[0])
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"build");
+        let elsewhere = ReleaseAsset {
+            name: "x.zip".into(),
+            browser_download_url: "https://github.com/x.zip".into(),
+        };
+        assert!(forgejo.open_asset(&elsewhere).unwrap().is_none());
+        let lookalike = ReleaseAsset {
+            name: "x.zip".into(),
+            browser_download_url: format!("{}.evil.example/x.zip", server.url),
+        };
+        assert!(forgejo.open_asset(&lookalike).unwrap().is_none());
+        // kept apart from upstream's releases of the same name
+        assert_ne!(
+            forgejo.release_cache_dir(),
+            GithubWeb::new(&server.url).release_cache_dir()
+        );
+    }
+
+    #[test]
+    fn links_and_commits_come_from_the_fork() {
+        let server = mock();
+        let sources = Sources(vec![
+            Box::new(GithubWeb::new(GITHUB_WEB)),
+            Box::new(Forgejo::new(&server.url, REPO, None)),
+        ]);
+        let release = Forgejo::new(&server.url, REPO, None)
+            .release("fork-nightly-117")
+            .unwrap()
+            .unwrap();
+        let fork = sources.of_release(&release).unwrap();
+        assert_eq!(fork.repo_url(), Some(format!("{}/{}", server.url, REPO)));
+        assert_eq!(
+            fork.pull_url(12),
+            Some(format!("{}/{}/pulls/12", server.url, REPO))
+        );
+        let compare = fork
+            .compare("fork-nightly-116", "fork-nightly-117")
+            .unwrap()
+            .unwrap();
+        assert_eq!(compare.commits[0].commit.message, "Faster monsters (#12)");
+
+        let upstream = GithubRelease {
+            html_url: format!("{}/{}/releases/tag/x", GITHUB_WEB, CDDA_REPO),
+            ..release
+        };
+        assert_eq!(
+            sources.of_release(&upstream).unwrap().describe(),
+            GithubWeb::new(GITHUB_WEB).describe()
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+pub fn copy_dir_all(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// All files under `root`, relative to it
+pub fn list_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    fn walk(root: &Path, dir: &Path, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                walk(root, &entry.path(), out)?;
+            } else {
+                out.push(entry.path().strip_prefix(root)?.to_path_buf());
+            }
+        }
+        Ok(())
+    }
+    let mut out = vec![];
+    if root.exists() {
+        walk(root, root, &mut out)?;
+    }
+    out.sort();
+    Ok(out)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange {
+    Added(PathBuf),
+    Removed(PathBuf),
+    Changed(PathBuf),
+}
+
+/// File-level differences going from `old` to `new`
+pub fn diff_trees(old: &Path, new: &Path) -> anyhow::Result<Vec<TreeChange>> {
+    let old_files = list_files(old)?;
+    let new_files = list_files(new)?;
+    let old_set = old_files.iter().collect::<std::collections::BTreeSet<_>>();
+    let new_set = new_files.iter().collect::<std::collections::BTreeSet<_>>();
+    let mut out = vec![];
+    for f in old_set.union(&new_set) {
+        let change = match (old_set.contains(f), new_set.contains(f)) {
+            (true, false) => TreeChange::Removed(f.to_path_buf()),
+            (false, true) => TreeChange::Added(f.to_path_buf()),
+            _ => {
+                if std::fs::read(old.join(f))? == std::fs::read(new.join(f))? {
+                    continue;
+                }
+                TreeChange::Changed(f.to_path_buf())
+            }
+        };
+        out.push(change);
+    }
+    Ok(out)
+}
@@ -0,0 +1,257 @@
+//! Release metadata scraped from github.com's `releases.atom` and release pages, for when the REST API
+//! is rate limited. These pages don't count against the API quota.
+
+use crate::release_hub::{GitTag, GithubRelease, ReleaseAsset, CDDA_REPO};
+use crate::release_source::{AssetStream, ReleaseSource};
+
+pub const GITHUB_WEB: &str = "https://github.com";
+
+pub struct GithubWeb {
+    base: String,
+    agent: ureq::Agent,
+}
+
+impl GithubWeb {
+    pub fn new(base: &str) -> Self {
+        Self {
+            base: base.trim_end_matches('/').to_string(),
+            agent: ureq::builder().user_agent("moxian-bisector-thingy").build(),
+        }
+    }
+
+    fn get(&self, path: &str) -> anyhow::Result<Option<String>> {
+        let url = format!("{}/{}/{}", self.base, CDDA_REPO, path);
+        match self.agent.get(&url).call() {
+            Ok(response) => Ok(Some(response.into_string()?)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Tag names of the entries of the feed, newest first. Only the latest few releases are in it.
+pub fn parse_atom(feed: &str) -> Vec<String> {
+    let entry = regex::Regex::new(r"(?s)<entry>.*?</entry>").unwrap();
+    let link = regex::Regex::new(r#"<link[^>]*href="[^"]*/releases/tag/([^"]+)""#).unwrap();
+    entry
+        .find_iter(feed)
+        .filter_map(|e| Some(link.captures(e.as_str())?[1].to_string()))
+        .collect()
+}
+
+/// When `tag` was published, and the commit it was built from. Only its own release card is looked at,
+/// with the commit being the one linked after the tag, as pages list other releases and commits too.
+pub fn parse_release_page(html: &str, tag: &str) -> (Option<String>, Option<String>) {
+    let section = regex::Regex::new(r"(?s)<section\b.*?</section>").unwrap();
+    let time = regex::Regex::new(r#"<(?:local|relative)-time[^>]*\sdatetime="([^"]+)""#).unwrap();
+    let commit = regex::Regex::new(r#"href="/[^"]+/commit/([0-9a-f]{40})""#).unwrap();
+    let tag_link = format!("/tree/{}\"", tag);
+    let Some(card) = section
+        .find_iter(html)
+        .map(|s| s.as_str())
+        .find(|s| s.contains(&tag_link))
+    else {
+        return (None, None);
+    };
+    let after_tag = &card[card.find(&tag_link).unwrap()..];
+    (
+        time.captures(card).map(|c| c[1].to_string()),
+        commit.captures(after_tag).map(|c| c[1].to_string()),
+    )
+}
+
+/// The release's uploaded assets, as (name, absolute url). The source code archives aren't assets
+pub fn parse_expanded_assets(html: &str, base: &str) -> Vec<ReleaseAsset> {
+    let link = regex::Regex::new(r#"href="(/[^"]+/releases/download/[^"]+)""#).unwrap();
+    let mut out: Vec<ReleaseAsset> = vec![];
+    for c in link.captures_iter(html) {
+        let path = &c[1];
+        let name = path.rsplit('/').next().unwrap().to_string();
+        if out.iter().all(|a| a.name != name) {
+            out.push(ReleaseAsset {
+                name,
+                browser_download_url: format!("{}{}", base, path),
+            });
+        }
+    }
+    out
+}
+
+impl ReleaseSource for GithubWeb {
+    fn describe(&self) -> String {
+        format!("{} release pages", self.base)
+    }
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+        let tags = self.get("releases.atom")?.map(|feed| parse_atom(&feed));
+        Ok(tags.map(|tags| tags.into_iter().map(GitTag::named).collect()))
+    }
+    fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+        let Some(page) = self.get(&format!("releases/tag/{}", tag))? else {
+            return Ok(None);
+        };
+        let (published_at, commit) = parse_release_page(&page, tag);
+        // a release without its assets is no use, and would get cached as such
+        let assets = self
+            .get(&format!("releases/expanded_assets/{}", tag))?
+            .map(|html| parse_expanded_assets(&html, &self.base))
+            .unwrap_or_default();
+        anyhow::ensure!(
+            !assets.is_empty(),
+            "no assets on the release page of {}",
+            tag
+        );
+        Ok(Some(GithubRelease {
+            id: 0,
+            published_at: published_at.unwrap_or_default(),
+            tag_name: tag.to_string(),
+            assets,
+            html_url: format!("{}/{}/releases/tag/{}", self.base, CDDA_REPO, tag),
+            target_commitish: commit.unwrap_or_default(),
+            local: false,
+        }))
+    }
+    fn open_asset(&self, _asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+        // the download urls aren't the API's, `github` handles them
+        Ok(None)
+    }
+    fn repo_url(&self) -> Option<String> {
+        Some(format!("{}/{}", self.base, CDDA_REPO))
+    }
+    fn pull_url(&self, number: u32) -> Option<String> {
+        Some(format!("{}/{}/pull/{}", self.base, CDDA_REPO, number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::release_source::GithubApi;
+    use std::collections::HashMap;
+
+    const TAG: &str = "cdda-experimental-2025-03-02-0012";
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fixtures/github_web")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_fixture_pages() {
+        assert_eq!(
+            parse_atom(&fixture("releases.atom")),
+            vec![TAG, "cdda-experimental-2025-03-01-1833", "0.H-RELEASE"]
+        );
+        assert_eq!(
+            parse_release_page(&fixture("release_page.html"), TAG),
+            (
+                Some("2025-03-02T00:31:17Z".to_string()),
+                Some("1f0e2d3c4b5a69788796a5b4c3d2e1f0a9b8c7d6".to_string())
+            )
+        );
+        // the release list has the newer release's card, and its commit, first
+        assert_eq!(
+            parse_release_page(
+                &fixture("releases.html"),
+                "cdda-experimental-2025-03-01-1833"
+            ),
+            (
+                Some("2025-03-01T18:52:40Z".to_string()),
+                Some("9c8b7a6f5e4d3c2b1a0f9e8d7c6b5a4f3e2d1c0b".to_string())
+            )
+        );
+        assert_eq!(
+            parse_release_page(
+                &fixture("releases.html"),
+                "cdda-experimental-2025-02-28-0000"
+            ),
+            (None, None)
+        );
+        let assets = parse_expanded_assets(&fixture("expanded_assets.html"), GITHUB_WEB);
+        let names = assets.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "cdda-linux-curses-x64-2025-03-02-0012.tar.gz",
+                "cdda-linux-tiles-x64-2025-03-02-0012.tar.gz",
+                "cdda-osx-tiles-universal-2025-03-02-0012.dmg",
+                "cdda-windows-tiles-x64-msvc-2025-03-02-0012.zip"
+            ]
+        );
+        assert_eq!(
+            assets[3].browser_download_url,
+            format!(
+                "https://github.com/CleverRaven/Cataclysm-DDA/releases/download/{}/{}",
+                TAG, names[3]
+            )
+        );
+    }
+
+    #[test]
+    fn falls_back_to_web_pages_when_rate_limited() {
+        let page = |name: &str| (200, fixture(name).into_bytes());
+        let server = crate::test_server::serve(HashMap::from([
+            (
+                format!("/repos/{}/releases/tags/{}", CDDA_REPO, TAG),
+                (403, br#"{"message": "API rate limit exceeded"}"#.to_vec()),
+            ),
+            (
+                format!("/{}/releases/tag/{}", CDDA_REPO, TAG),
+                page("release_page.html"),
+            ),
+            (
+                format!("/{}/releases/expanded_assets/{}", CDDA_REPO, TAG),
+                page("expanded_assets.html"),
+            ),
+        ]));
+        let api = GithubApi::new(&server.url, &server.url);
+        let release = api.release(TAG).unwrap().unwrap();
+        assert_eq!(release.tag_name, TAG);
+        assert_eq!(release.published_at, "2025-03-02T00:31:17Z");
+        assert_eq!(release.assets.len(), 4);
+        assert!(release.assets[3]
+            .browser_download_url
+            .starts_with(&server.url));
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].starts_with("GET /repos/"));
+        assert_eq!(requests.len(), 3);
+    }
+
+    #[test]
+    fn releases_without_assets_are_errors() {
+        let page = |name: &str| (200, fixture(name).into_bytes());
+        let server = crate::test_server::serve(HashMap::from([
+            (
+                format!("/{}/releases/tag/{}", CDDA_REPO, TAG),
+                page("release_page.html"),
+            ),
+            (
+                format!("/{}/releases/expanded_assets/{}", CDDA_REPO, TAG),
+                (500, vec![]),
+            ),
+            (
+                format!(
+                    "/{}/releases/tag/{}",
+                    CDDA_REPO, "cdda-experimental-2025-03-01-1833"
+                ),
+                page("releases.html"),
+            ),
+            (
+                format!(
+                    "/{}/releases/expanded_assets/{}",
+                    CDDA_REPO, "cdda-experimental-2025-03-01-1833"
+                ),
+                (
+                    200,
+                    b"<div class=\"Box Box--condensed mt-3\"><ul></ul></div>".to_vec(),
+                ),
+            ),
+        ]));
+        let web = GithubWeb::new(&server.url);
+        assert!(web.release(TAG).is_err());
+        assert!(web.release("cdda-experimental-2025-03-01-1833").is_err());
+    }
+}
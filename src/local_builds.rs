@@ -0,0 +1,117 @@
+//! Builds that aren't GitHub releases (PR artifacts, CI builds of forks, own compiles),
+//! slotted into the release timeline under a pseudo-tag.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::release_hub::{GitTag, GithubRelease, ReleaseAsset};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct LocalBuild {
+    /// the pseudo-tag, `local-<file name>`
+    pub name: String,
+    /// an archive, or an already unpacked build
+    pub path: PathBuf,
+    pub datetime: chrono::NaiveDateTime,
+    #[serde(default)]
+    pub sha: Option<String>,
+}
+
+impl LocalBuild {
+    pub fn tag(&self) -> GitTag {
+        GitTag {
+            name: self.name.clone(),
+            datetime: Some(self.datetime),
+        }
+    }
+
+    /// Stands in for the GitHub release, with the path as the only asset
+    pub fn release(&self) -> GithubRelease {
+        let file_name = self.path.file_name().unwrap().to_string_lossy();
+        // named after the tag so that its unpack dir is unique, keeping the extension so it can be unpacked
+        let extension = match file_name.split_once('.') {
+            Some((_, ext)) if self.path.is_file() => format!(".{}", ext),
+            _ => String::new(),
+        };
+        GithubRelease {
+            id: 0,
+            published_at: self.datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            tag_name: self.name.clone(),
+            assets: vec![ReleaseAsset {
+                name: format!("{}{}", self.name, extension),
+                browser_download_url: format!("file://{}", self.path.to_string_lossy()),
+            }],
+            html_url: String::new(),
+            target_commitish: self.sha.clone().unwrap_or_default(),
+            local: true,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct LocalBuilds {
+    pub builds: Vec<LocalBuild>,
+}
+
+impl LocalBuilds {
+    fn file() -> PathBuf {
+        Path::new("cache/local_builds.json").into()
+    }
+    pub fn load() -> anyhow::Result<Self> {
+        let file = Self::file();
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(file)?)?)
+    }
+    fn save(&self) -> anyhow::Result<()> {
+        std::fs::File::create(Self::file())?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn add(
+        &mut self,
+        path: &Path,
+        datetime: chrono::NaiveDateTime,
+        sha: Option<String>,
+    ) -> anyhow::Result<LocalBuild> {
+        anyhow::ensure!(path.exists(), "{:?} doesn't exist", path);
+        let path = std::fs::canonicalize(path)?;
+        let stem = path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .split('.')
+            .next()
+            .unwrap()
+            .to_string();
+        let mut name = format!("local-{}", stem);
+        let mut n = 1;
+        while self.builds.iter().any(|b| b.name == name) {
+            n += 1;
+            name = format!("local-{}-{}", stem, n);
+        }
+        let build = LocalBuild {
+            name,
+            path,
+            datetime,
+            sha,
+        };
+        self.builds.push(build.clone());
+        self.save()?;
+        Ok(build)
+    }
+}
+
+/// `2024-01-31`, `2024-01-31-1530` (like the release tags) or `2024-01-31T15:30[:00]`
+pub fn parse_datetime(s: &str) -> anyhow::Result<chrono::NaiveDateTime> {
+    for pat in ["%Y-%m-%d-%H%M", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(s, pat) {
+            return Ok(dt);
+        }
+    }
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| anyhow::format_err!("can't parse {:?} as a date", s))?;
+    Ok(date.and_hms_opt(0, 0, 0).unwrap())
+}
@@ -1,8 +1,38 @@
 mod bisecting;
+mod cache;
+mod crash;
+mod datadiff;
+#[cfg(all(test, unix))]
+mod e2e_tests;
+mod fixups;
+mod forgejo;
+mod fsutil;
+mod github_web;
+mod local_builds;
+mod metrics;
+mod mirror;
+mod modbisect;
+mod oracle;
+mod overlays;
+mod partial_zip;
+mod pty_driver;
 mod release_hub;
+mod release_source;
+mod report;
+mod runs;
+mod saves;
+mod stats;
+mod store;
+#[cfg(test)]
+mod test_server;
+mod userdata_import;
+
+/// Held by the tests that depend on the current dir, as the state and caches are relative to it
+#[cfg(test)]
+static TEST_CWD: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 
@@ -23,6 +53,92 @@ struct Config {
     unpack_dir: std::path::PathBuf,
     userdata_dir: std::path::PathBuf,
     zip_extractor_path: std::path::PathBuf,
+    /// what a crashed launch offers to mark the version as (`Bad` or `Skip`)
+    #[serde(default)]
+    crash_verdict: Option<Goodness>,
+    /// keep a copy of the pinned world as each run left it, in the run's directory
+    #[serde(default)]
+    archive_saves: bool,
+    /// extra userdata fixup rules, on top of the built-in ones
+    #[serde(default)]
+    fixups: Vec<fixups::FixupRule>,
+    /// give every version its own userdir in `cache/userdirs/`, cloned from the template on first use
+    #[serde(default)]
+    isolate_userdata: bool,
+    /// what isolated userdirs are cloned from; `userdata_dir` itself if not set
+    #[serde(default)]
+    userdata_template_dir: Option<PathBuf>,
+    /// extra mods, tilesets and soundpacks copied into each activated build
+    #[serde(default)]
+    overlays: Vec<overlays::Overlay>,
+    /// what `auto` and `auto-bisect` use to judge a build
+    #[serde(default)]
+    oracle: Option<oracle::OracleConfig>,
+    /// only fetch the archive entries matching this glob (e.g. `data/json/**`) with range requests,
+    /// into `cache/partial/<tag>`. Enough for the data oracle, but such builds can't be launched
+    #[serde(default)]
+    partial_fetch: Option<String>,
+    /// asset name prefixes tried before the windows tiles builds, e.g. `cdda-linux-curses-x64` for `keystrokes`
+    #[serde(default)]
+    preferred_assets: Vec<String>,
+    /// what `cache gc` removes
+    #[serde(default)]
+    cache_policy: cache::CachePolicy,
+    /// unpack builds into hardlinks to a content-addressed store in `cache/store/`, so they share identical files
+    #[serde(default)]
+    dedup_builds: bool,
+    /// base urls of other instances' `serve`, tried before GitHub for release metadata and archives
+    #[serde(default)]
+    mirrors: Vec<String>,
+    /// where tags, release metadata and archives come from, by priority. Defaults to the `mirrors`, git and GitHub
+    #[serde(default)]
+    release_sources: Option<Vec<release_source::SourceConfig>>,
+    /// named alternatives to `release_sources`, e.g. upstream's and a fork's, picked with `profile`
+    #[serde(default)]
+    release_profiles: std::collections::BTreeMap<String, Vec<release_source::SourceConfig>>,
+    /// the game binary `run` and the oracles launch, relative to the build; whichever of the usual ones
+    /// the build has if not set
+    #[serde(default)]
+    game_executable: Option<String>,
+}
+impl Config {
+    fn userdata_template_dir(&self) -> &std::path::Path {
+        self.userdata_template_dir
+            .as_deref()
+            .unwrap_or(&self.userdata_dir)
+    }
+    /// The sources of the release profile `profile`, or `release_sources` when none is picked
+    fn release_sources(
+        &self,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Vec<release_source::SourceConfig>> {
+        if let Some(name) = profile {
+            return self.release_profiles.get(name).cloned().with_context(|| {
+                anyhow::format_err!("there's no release profile {:?} in the config", name)
+            });
+        }
+        Ok(match &self.release_sources {
+            Some(sources) => sources.clone(),
+            None => release_source::SourceConfig::defaults(&self.mirrors),
+        })
+    }
+}
+
+fn release_profile_file() -> PathBuf {
+    Path::new("cache/release_profile.json").into()
+}
+/// The release profile picked with `profile`, if any
+fn load_release_profile() -> anyhow::Result<Option<String>> {
+    let file = release_profile_file();
+    if !file.exists() {
+        return Ok(None);
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(file)?)?)
+}
+fn save_release_profile(profile: Option<&str>) -> anyhow::Result<()> {
+    std::fs::create_dir_all("cache").ok();
+    std::fs::write(release_profile_file(), serde_json::to_string(&profile)?)?;
+    Ok(())
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -39,10 +155,10 @@ impl ReleaseBlacklist {
             let out = Self {
                 release_tags: Default::default(),
             };
-            out.save().with_context(|| format!("saving blacklist?"))?;
+            out.save().context("saving blacklist?")?;
             return Ok(out);
         }
-        
+
         let out = serde_json::from_str(
             &std::fs::read_to_string(file).with_context(|| format!("reading {:?}", file))?,
         )?;
@@ -59,12 +175,110 @@ impl ReleaseBlacklist {
     }
 }
 
+/// Extracts `archive` into `out_dir` (which must not exist yet) with 7-zip
+fn unpack_archive(
+    zip_extractor: &std::path::Path,
+    archive: &std::path::Path,
+    out_dir: &std::path::Path,
+) -> anyhow::Result<()> {
+    let parent = out_dir.parent().unwrap();
+    std::fs::create_dir_all(parent)?;
+    let tmp_dir = parent.join("_unpack_tmp");
+    if tmp_dir.exists() {
+        std::fs::remove_dir_all(&tmp_dir)?;
+    }
+    extract(zip_extractor, archive, &tmp_dir)?;
+    // 7-zip only takes the .gz off a tarball, the linux builds then also wrap everything into a folder
+    let name = archive.to_string_lossy();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let tar_dir = parent.join("_unpack_tar_tmp");
+        if tar_dir.exists() {
+            std::fs::remove_dir_all(&tar_dir)?;
+        }
+        let tar = std::fs::read_dir(&tmp_dir)?
+            .next()
+            .with_context(|| anyhow::format_err!("{:?} is empty", archive))??
+            .path();
+        extract(zip_extractor, &tar, &tar_dir)?;
+        std::fs::remove_dir_all(&tmp_dir)?;
+        let children = std::fs::read_dir(&tar_dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match children.as_slice() {
+            [only] if only.is_dir() => {
+                std::fs::rename(only, &tmp_dir)?;
+                std::fs::remove_dir_all(&tar_dir)?;
+            }
+            _ => std::fs::rename(&tar_dir, &tmp_dir)?,
+        }
+    }
+    std::fs::rename(tmp_dir, out_dir)?;
+    Ok(())
+}
+
+fn extract(
+    zip_extractor: &std::path::Path,
+    archive: &std::path::Path,
+    out_dir: &std::path::Path,
+) -> anyhow::Result<()> {
+    // assuming 7-zip
+    let status = std::process::Command::new(zip_extractor)
+        .args(["x", "-aou"]) // extract, overwrite always
+        .args(["-bb0"])
+        // .args(["-bd"]) // disable output progress
+        .arg(format!("-o{}", out_dir.to_string_lossy())) //output dir
+        .arg(archive)
+        .status()?;
+    // 1 is a warning, e.g. about timestamps it couldn't set
+    anyhow::ensure!(
+        matches!(status.code(), Some(0 | 1)),
+        "failed to unpack {:?}: {}",
+        archive,
+        status
+    );
+    Ok(())
+}
+
 fn asset_unpack_dir(cfg: &Config, asset: &ReleaseAsset) -> PathBuf {
     cfg.unpack_dir.join(asset.name.split(".").next().unwrap())
 }
 
+fn partial_unpack_dir(tag: &str) -> PathBuf {
+    Path::new("cache/partial").join(tag)
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct TrackEntry {
+    tag: String,
+    goodness: Goodness,
+    #[serde(default)]
+    asset: Option<String>,
+    #[serde(default)]
+    marked_at: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+    #[serde(default)]
+    exit_status: Option<String>,
+    /// `cache/runs/..` directory of the launch this mark is based on
+    #[serde(default)]
+    run: Option<String>,
+}
+impl TrackEntry {
+    fn new(tag: String, goodness: Goodness) -> Self {
+        Self {
+            tag,
+            goodness,
+            asset: None,
+            marked_at: None,
+            note: None,
+            exit_status: None,
+            run: None,
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
-struct Track(Vec<(String, Goodness)>);
+struct Track(Vec<TrackEntry>);
 impl Track {
     fn new() -> Self {
         Track(vec![])
@@ -72,7 +286,13 @@ impl Track {
     fn load() -> anyhow::Result<Self> {
         let track_f = std::path::Path::new("cache/track.json");
         if track_f.exists() {
-            return Ok(serde_json::from_str(&std::fs::read_to_string(track_f)?)?);
+            let text = std::fs::read_to_string(track_f)?;
+            // older tracks were plain (tag, goodness) pairs
+            if let Ok(old) = serde_json::from_str::<Vec<(String, Goodness)>>(&text) {
+                let entries = old.into_iter().map(|(t, g)| TrackEntry::new(t, g));
+                return Ok(Track(entries.collect()));
+            }
+            return Ok(serde_json::from_str(&text)?);
         };
         Ok(Track::new())
     }
@@ -82,15 +302,43 @@ impl Track {
         Ok(())
     }
     fn is_tag_skipped(&self, tag: &GitTag) -> bool {
-        let marked = self.0.iter().find(|t| t.0 == tag.name);
+        let marked = self.0.iter().find(|t| t.tag == tag.name);
         let Some(marked) = marked else {
             return false;
         };
-        return marked.1 == Goodness::Skip;
+        marked.goodness == Goodness::Skip
+    }
+    /// (latest good, earliest bad) as of the first `n` marks
+    ///
+    /// Marks are ordered by when they were built, as placed on `timeline`;
+    /// tag names don't sort by date once local builds and forks are involved.
+    fn bounds_after(&self, n: usize, timeline: &[GitTag]) -> (Option<String>, Option<String>) {
+        let when = |tag: &String| {
+            let datetime = match timeline.iter().find(|t| &t.name == tag) {
+                Some(t) => t.try_tag_datetime(),
+                None => GitTag::named(tag.as_str()).try_tag_datetime(),
+            };
+            datetime.map(|d| (d, tag.clone()))
+        };
+        let entries = &self.0[..n];
+        let latest_good = entries
+            .iter()
+            .filter(|e| e.goodness == Goodness::Good)
+            .filter_map(|e| when(&e.tag))
+            .max();
+        let earliest_bad = entries
+            .iter()
+            .filter(|e| e.goodness == Goodness::Bad)
+            .filter_map(|e| when(&e.tag))
+            .min();
+        (latest_good.map(|(_, t)| t), earliest_bad.map(|(_, t)| t))
+    }
+    fn bounds(&self, timeline: &[GitTag]) -> (Option<String>, Option<String>) {
+        self.bounds_after(self.0.len(), timeline)
     }
 }
 
-fn select_best_asset(release: &GithubRelease) -> &ReleaseAsset {
+fn select_best_asset<'a>(cfg: &Config, release: &'a GithubRelease) -> &'a ReleaseAsset {
     let prio_list = vec![
         "cdda-windows-tiles-x64-msvc",
         "cdda-windows-with-graphics-x64",
@@ -98,23 +346,28 @@ fn select_best_asset(release: &GithubRelease) -> &ReleaseAsset {
         "cdda-windows-tiles",
         "cdda-windows-with-graphics",
     ];
-    for prio in prio_list {
+    let preferred = cfg.preferred_assets.iter().map(|p| p.as_str());
+    for prio in preferred.chain(prio_list) {
         let candidates = release
             .assets
             .iter()
             .filter(|a| a.name.starts_with(prio))
             .collect::<Vec<_>>();
         if let Some(a) = candidates.first() {
-            return *a;
+            return a;
         }
     }
+    // local builds only have the one
+    if let (true, [only]) = (release.local, release.assets.as_slice()) {
+        return only;
+    }
     panic!("this should never happen (release: {:?})", release);
 }
 use bisecting::Roundness;
-fn select_earlier_release<'a>(
-    releases: &'a ReleaseHub,
+fn select_earlier_release(
+    releases: &ReleaseHub,
     rough_date: Option<chrono::NaiveDate>,
-) -> anyhow::Result<&'a GitTag> {
+) -> anyhow::Result<&GitTag> {
     // let earliest_release = releases.find_tag(earliest_tag);
     // let earliest_date = earliest_release.tag_datetime().date();
 
@@ -154,33 +407,52 @@ fn select_earlier_release<'a>(
     let earlier_release = releases
         .tags_list
         .iter()
-        .filter(|r| r.tag_datetime().date() == earlier)
-        .last()
+        .rfind(|r| r.tag_datetime().date() == earlier)
         .with_context(|| anyhow::format_err!("no releases match the date of {:?}", earlier))?;
     Ok(earlier_release)
     // let earlier_release = earlier_release.unwrap();
     // Ok(earlier_release)
 }
 
+fn isolated_userdata_dir(tag: &str) -> PathBuf {
+    std::path::Path::new("cache/userdirs").join(tag)
+}
+
 struct BisectState {
     config: Config,
     releases: ReleaseHub,
     active_install: Option<(GithubRelease, ReleaseAsset)>,
     track: Track,
+    last_run: Option<runs::Run>,
+    pinned_save: Option<saves::PinnedSave>,
+    save_versions: saves::SaveVersions,
+    /// while set, `next` and `mark` drive the mod list bisection instead of the version one
+    mod_bisect: Option<modbisect::ModBisect>,
+    /// which of `release_profiles` the releases come from, `release_sources` if none
+    release_profile: Option<String>,
 }
 impl BisectState {
     fn new() -> anyhow::Result<Self> {
         let config: Config = json5::from_str(&std::fs::read_to_string("config.json5")?)?;
-        let releases = ReleaseHub::load().with_context(|| format!("grabbing releases"))?;
+        let release_profile = load_release_profile()?;
+        let releases = ReleaseHub::load(&config.release_sources(release_profile.as_deref())?)
+            .context("grabbing releases")?;
+        // a session that ended without `quit` leaves its overlays in
+        overlays::revert_all(&config.unpack_dir)?;
         let track = Track::load()?;
         let mut out = Self {
             config,
             releases,
             active_install: None,
             track: Track::load()?,
+            last_run: None,
+            pinned_save: saves::PinnedSave::load()?,
+            save_versions: saves::SaveVersions::load()?,
+            mod_bisect: modbisect::ModBisect::load()?,
+            release_profile,
         };
-        if let Some((v, _)) = track.0.last().cloned() {
-            out.activate_tag(&v)?;
+        if let Some(last) = track.0.last() {
+            out.activate_tag(&last.tag)?;
         };
         Ok(out)
     }
@@ -189,60 +461,168 @@ impl BisectState {
     }
     fn activate_release(&mut self, release: &GithubRelease) -> anyhow::Result<()> {
         // let active_version = &self.releases[0].clone();
-        let asset = select_best_asset(release);
+        let asset = select_best_asset(&self.config, release);
+        if let Some(glob) = &self.config.partial_fetch {
+            anyhow::ensure!(
+                !release.local,
+                "local builds can't be partially fetched, unset `partial_fetch` to use them"
+            );
+            anyhow::ensure!(
+                asset.name.ends_with(".zip"),
+                "partial fetching only works for zip assets, not {:?}",
+                asset.name
+            );
+            partial_zip::fetch(
+                &asset.browser_download_url,
+                glob,
+                &partial_unpack_dir(&release.tag_name),
+            )?;
+            self.active_install = Some((release.clone(), asset.clone()));
+            return Ok(());
+        }
+        self.revert_overlays()?;
         self.activate_asset(asset)?;
+        overlays::apply(
+            &asset_unpack_dir(&self.config, asset),
+            &self.config.overlays,
+            &self.config.zip_extractor_path,
+        )?;
+        cache::Usage::touch(&release.tag_name, &asset.name)?;
         self.active_install = Some((release.clone(), asset.clone()));
         Ok(())
     }
+    fn revert_overlays(&self) -> anyhow::Result<()> {
+        if let Some((_release, asset)) = &self.active_install {
+            overlays::revert(&asset_unpack_dir(&self.config, asset))?;
+        }
+        Ok(())
+    }
     fn activate_asset(&mut self, asset: &ReleaseAsset) -> anyhow::Result<()> {
         log::info!("Activating version {:?}", asset.name);
         std::fs::create_dir_all(&self.config.distr_dir)?;
-        let distr_file = &self.config.distr_dir.join(&asset.name);
+        // local archives are unpacked from where they are, local dirs copied so that overlays don't end up in them
+        let local = asset
+            .browser_download_url
+            .strip_prefix("file://")
+            .map(PathBuf::from);
+        if let Some(dir) = local.as_ref().filter(|l| l.is_dir()) {
+            let unpacked_dir = asset_unpack_dir(&self.config, asset);
+            if !unpacked_dir.exists() {
+                log::info!("Copying {:?} -> {:?}", dir, unpacked_dir);
+                fsutil::copy_dir_all(dir, &unpacked_dir)?;
+                if self.config.dedup_builds {
+                    store::import(&unpacked_dir)?;
+                }
+            }
+            return Ok(());
+        }
+        let distr_file = &local.unwrap_or_else(|| self.config.distr_dir.join(&asset.name));
         if !distr_file.exists() {
-            log::info!(
-                "Downloading {} -> {}..",
-                asset.browser_download_url,
-                distr_file.to_string_lossy()
-            );
-            let mut buf = vec![];
-            ureq::get(&asset.browser_download_url)
-                .call()?
-                .into_reader()
-                .read_to_end(&mut buf)?;
-            std::fs::File::create(distr_file)?.write_all(&buf)?;
-            log::info!("..done");
+            self.releases.sources.download(asset, distr_file)?;
         }
 
         let unpacked_dir = self
             .config
             .unpack_dir
             .join(asset.name.split(".").next().unwrap());
-        anyhow::ensure!(asset.name.ends_with(".zip"));
+        anyhow::ensure!(
+            [".zip", ".tar.gz", ".tgz"]
+                .iter()
+                .any(|ext| asset.name.ends_with(ext)),
+            "don't know how to unpack {:?}",
+            asset.name
+        );
         if !unpacked_dir.exists() {
             log::info!(
                 "Unpacking {} -> {} ... ",
                 distr_file.to_string_lossy(),
                 unpacked_dir.to_string_lossy()
             );
-            let unpacked_dir_tmp = &self.config.unpack_dir.join("_unpack_tmp");
-            if unpacked_dir_tmp.exists() {
-                std::fs::remove_dir_all(unpacked_dir_tmp)?;
-            }
-            // assuming 7-zip
-            std::process::Command::new(&self.config.zip_extractor_path)
-                .args(["x", "-aou"]) // extract, overwrite always
-                .args(["-bb0"])
-                // .args(["-bd"]) // disable output progress
-                .arg(format!("-o{}", unpacked_dir_tmp.to_string_lossy())) //output dir
-                .arg(distr_file)
-                .status()?;
-            std::fs::rename(unpacked_dir_tmp, unpacked_dir)?;
+            unpack_archive(&self.config.zip_extractor_path, distr_file, &unpacked_dir)?;
+            if self.config.dedup_builds {
+                store::import(&unpacked_dir)?;
+            }
             log::info!("..done");
         }
         Ok(())
     }
 
-    fn launch(&self) -> anyhow::Result<()> {
+    /// Warns if the pinned save is likely too new for the active version.
+    /// Returns whether the save is suspected to be unloadable.
+    fn check_save_compat(&mut self) -> anyhow::Result<bool> {
+        let (Some(pinned), Some((release, _))) = (&self.pinned_save, &self.active_install) else {
+            return Ok(false);
+        };
+        let Some(version) = pinned.snapshot_version() else {
+            log::warn!("couldn't read the save version of {:?}", pinned.world);
+            return Ok(false);
+        };
+        let tag = release.tag_name.clone();
+        match self
+            .save_versions
+            .check(&self.releases, &tag, version, pinned.written_at)
+        {
+            saves::SaveCompat::Compatible => Ok(false),
+            saves::SaveCompat::Unknown => {
+                log::info!(
+                    "Don't know yet whether {} can load save version {}",
+                    tag,
+                    version
+                );
+                Ok(false)
+            }
+            saves::SaveCompat::PredatesSave { written_at } => {
+                println!(
+                    "Warning: the pinned save (version {}) was written on {}, after {} was built. It may be too new for it",
+                    version,
+                    written_at.format("%Y-%m-%d"),
+                    tag
+                );
+                Ok(true)
+            }
+            saves::SaveCompat::Incompatible { nearest } => {
+                println!(
+                    "Warning: the pinned save has version {}, which {} likely can't load",
+                    version, tag
+                );
+                if let Some(nearest) = nearest {
+                    if ask(&format!("Switch to {} instead?", nearest)) {
+                        self.activate_tag(&nearest)?;
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Gets the active version's userdir ready for a run: pinned save, fixups, mod set.
+    fn prepare_userdata(&self) -> anyhow::Result<PathBuf> {
+        let userdata_dir = self.userdata_dir()?;
+        if !userdata_dir.exists() {
+            if self.config.isolate_userdata && self.config.userdata_template_dir().exists() {
+                log::info!("Creating userdir {:?} from the template", userdata_dir);
+                fsutil::copy_dir_all(self.config.userdata_template_dir(), &userdata_dir)?;
+            } else {
+                std::fs::create_dir_all(&userdata_dir)?;
+            }
+        };
+        if let Some(pinned) = &self.pinned_save {
+            log::info!("Restoring pinned save {:?}", pinned.world);
+            pinned.restore(&userdata_dir)?;
+        }
+        self.apply_fixups()?;
+        if let Some(mod_bisect) = &self.mod_bisect {
+            mod_bisect.write_mods(&userdata_dir)?;
+        }
+        Ok(userdata_dir)
+    }
+    fn launch(&mut self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.config.partial_fetch.is_none(),
+            "builds are only partially fetched (`partial_fetch` is set), they can't be launched"
+        );
+        let save_suspect = self.check_save_compat()?;
         let active_dir = asset_unpack_dir(
             &self.config,
             &self
@@ -252,71 +632,173 @@ impl BisectState {
                 .1,
         );
         let game_dir = &active_dir;
-        let userdata_dir = &self.config.userdata_dir;
-        if !userdata_dir.exists() {
-            std::fs::create_dir_all(userdata_dir)?;
+        let userdata_dir = &self.prepare_userdata()?;
+        let (release, asset) = self.active_install.as_ref().unwrap();
+        let executable = oracle::game_executable(game_dir, self.config.game_executable.as_deref())?;
+        let debug_log_start = crash::debug_log_len(userdata_dir);
+        let launched_at = std::time::SystemTime::now();
+        let launched_at_local = chrono::Local::now();
+        let run_dir = runs::new_run_dir(&release.tag_name, &launched_at_local)?;
+        let spawn = || -> std::io::Result<std::process::Child> {
+            std::process::Command::new(&executable)
+                .args(["--basepath", &game_dir.to_string_lossy()])
+                .args(["--userdir", &userdata_dir.to_string_lossy()])
+                .stdout(std::fs::File::create(run_dir.join("stdout.txt"))?)
+                .stderr(std::fs::File::create(run_dir.join("stderr.txt"))?)
+                .spawn()
+        };
+        let mut proc = match spawn() {
+            Ok(proc) => proc,
+            Err(e) => {
+                runs::discard(&run_dir);
+                return Err(e).with_context(|| {
+                    anyhow::format_err!("game dir: {}", game_dir.as_os_str().to_string_lossy())
+                });
+            }
         };
-        let mut proc = std::process::Command::new(game_dir.join("cataclysm-tiles.exe"))
-            .args(["--basepath", &game_dir.to_string_lossy()])
-            .args(["--userdir", &userdata_dir.to_string_lossy()])
-            .spawn()
-            .with_context(|| {
-                anyhow::format_err!("game dir: {}", game_dir.as_os_str().to_string_lossy())
-            })?;
-        proc.wait()?;
+        let status = proc.wait()?;
+        let exit = crash::describe_exit(&status);
+        println!("Game exited with {}", exit);
+        let record = runs::RunRecord {
+            tag: release.tag_name.clone(),
+            asset: asset.name.clone(),
+            launched_at: launched_at_local.format("%Y-%m-%d %H:%M:%S").to_string(),
+            duration_secs: launched_at.elapsed().unwrap_or_default().as_secs_f64(),
+            exit_status: exit.clone(),
+        };
+        let logs = crash::fresh_logs(userdata_dir, launched_at);
+        let run = runs::finish(&run_dir, record, userdata_dir, &logs)?;
+        if let Some(pinned) = &self.pinned_save {
+            if let Some(version) = pinned.written_version(userdata_dir, launched_at) {
+                self.save_versions.record(&run.record.tag, version)?;
+            }
+            if self.config.archive_saves {
+                pinned.archive(userdata_dir, &run_dir.join("save"))?;
+            }
+        }
+        println!("Run output saved to {}", run.dir.to_string_lossy());
+        self.last_run = Some(run);
+        if !status.success() {
+            self.handle_crash(launched_at, debug_log_start, &exit)?;
+        } else if save_suspect
+            && ask("Did the pinned save fail to load? Mark this version as Skip?")
+        {
+            self.mark(Goodness::Skip, Some("can't load the pinned save"))?;
+        }
         Ok(())
     }
 
-    fn mark_good(&mut self) -> anyhow::Result<()> {
-        let (release, _asset) = self.active_install.as_ref().unwrap();
-        self.track
-            .0
-            .push((release.tag_name.clone(), Goodness::Good));
-        self.track.save()?;
-        Ok(())
-    }
-    fn mark_bad(&mut self) -> anyhow::Result<()> {
-        let (release, _asset) = self.active_install.as_ref().unwrap();
-        self.track.0.push((release.tag_name.clone(), Goodness::Bad));
-        self.track.save()?;
+    fn handle_crash(
+        &mut self,
+        launched_at: std::time::SystemTime,
+        debug_log_start: u64,
+        exit: &str,
+    ) -> anyhow::Result<()> {
+        let evidence = crash::collect_evidence(&self.userdata_dir()?, launched_at, debug_log_start);
+        for (file, lines) in &evidence {
+            println!("--- {} ---", file.to_string_lossy());
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        if evidence.is_empty() {
+            println!("No crash.log or debug.log errors found.");
+        }
+        let verdict = self.config.crash_verdict.unwrap_or(Goodness::Skip);
+        if ask(&format!(
+            "The game crashed ({}). Mark it as {:?}?",
+            exit, verdict
+        )) {
+            self.mark(verdict, Some(&format!("crashed ({})", exit)))?;
+        }
         Ok(())
     }
-    fn mark_skip(&mut self) -> anyhow::Result<()> {
-        let (release, _asset) = self.active_install.as_ref().unwrap();
-        self.track
-            .0
-            .push((release.tag_name.clone(), Goodness::Skip));
+
+    fn mark(&mut self, goodness: Goodness, note: Option<&str>) -> anyhow::Result<()> {
+        if let Some(mod_bisect) = &mut self.mod_bisect {
+            mod_bisect.mark(goodness)?;
+            println!(
+                "Smallest failing mod set so far: {:?}",
+                mod_bisect.failing()
+            );
+            return Ok(());
+        }
+        let (release, asset) = self.active_install.as_ref().unwrap();
+        let last_run = self
+            .last_run
+            .as_ref()
+            .filter(|r| r.record.tag == release.tag_name);
+        self.track.0.push(TrackEntry {
+            tag: release.tag_name.clone(),
+            goodness,
+            asset: Some(asset.name.clone()),
+            marked_at: Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+            note: note.map(|n| n.to_string()),
+            exit_status: last_run.map(|r| r.record.exit_status.clone()),
+            run: last_run.map(|r| r.dir.to_string_lossy().to_string()),
+        });
         self.track.save()?;
         Ok(())
     }
-    fn mark_blacklist(&mut self) -> anyhow::Result<()> {
+    fn mark_blacklist(&mut self, note: Option<&str>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.mod_bisect.is_none(),
+            "only game versions can be blacklisted. Use `mark skip` for a mod set that can't be judged"
+        );
         let (release, _asset) = self.active_install.clone().unwrap();
-        self.mark_skip()?;
+        self.mark(Goodness::Skip, note)?;
         self.releases.mark_blacklist(&release)?;
         Ok(())
     }
     fn show_track(&self) -> anyhow::Result<()> {
-        for (tag, good) in &self.track.0 {
-            println!("{} - {:?}", tag, good);
+        for entry in &self.track.0 {
+            print!("{} - {:?}", entry.tag, entry.goodness);
+            if let Some(marked_at) = &entry.marked_at {
+                print!(" (at {})", marked_at);
+            }
+            if let Some(exit) = &entry.exit_status {
+                print!(" [{}]", exit);
+            }
+            if let Some(note) = &entry.note {
+                print!(" - {}", note);
+            }
+            if let Some(run) = &entry.run {
+                print!(" (run: {})", run);
+            }
+            println!();
         }
         Ok(())
     }
-    fn advance(&mut self, args: Option<&str>) -> anyhow::Result<()> {
-        let latest_good = self
-            .track
-            .0
-            .iter()
-            .filter(|(_, g)| g == &Goodness::Good)
-            .map(|(r, _)| r)
-            .max();
-        let earliest_bad = self
-            .track
-            .0
-            .iter()
-            .filter(|(_, g)| g == &Goodness::Bad)
-            .map(|(r, _)| r)
-            .min();
-        if earliest_bad.is_none() {
+    fn show_runs(&self, tag: Option<&str>) -> anyhow::Result<()> {
+        let tag = match tag {
+            Some(t) => t.to_string(),
+            None => {
+                let (release, _asset) = self
+                    .active_install
+                    .as_ref()
+                    .with_context(|| anyhow::format_err!("no active install"))?;
+                release.tag_name.clone()
+            }
+        };
+        for run in runs::list(&tag)? {
+            println!(
+                "{} - {} for {:.0}s, {} ({})",
+                run.record.tag,
+                run.record.launched_at,
+                run.record.duration_secs,
+                run.record.exit_status,
+                run.dir.to_string_lossy()
+            );
+        }
+        Ok(())
+    }
+    /// Activates the next candidate. Returns false once the bisection is finished.
+    fn advance(&mut self, args: Option<&str>) -> anyhow::Result<bool> {
+        if self.mod_bisect.is_some() {
+            return self.advance_mods();
+        }
+        let (latest_good, earliest_bad) = self.track.bounds(&self.releases.tags_list);
+        let Some(earliest_bad) = earliest_bad else {
             log::info!("No bad versions recorded... Trying latest installed.");
             let installed = self.find_freshest_install_tag();
             log::debug!("latest installed is {:?}", installed);
@@ -327,65 +809,198 @@ impl BisectState {
                 latest = self.releases.tags_list.first().unwrap();
             };
 
-            let release = self.releases.get_release(latest).clone();
+            let release = self.releases.get_release(latest)?;
             self.activate_release(&release)?;
-            return Ok(());
-        } else if latest_good.is_none() {
+            return Ok(true);
+        };
+        let Some(latest_good) = latest_good else {
             let mut ddays = 7;
             if let Some(args) = args {
-                if args.ends_with("d") {
-                    ddays = args[..args.len() - 1].parse()?
+                if let Some(days) = args.strip_suffix("d") {
+                    ddays = days.parse()?
                 }
             }
             println!("No good versions recorded. Trying {} days earlier", ddays);
-            let earliest_tag = &self.track.0.last().unwrap().0;
-            let approx_date = self.releases.find_tag(earliest_tag).tag_datetime().date();
+            let earliest_tag = &self.track.0.last().unwrap().tag;
+            let approx_date = self.releases.find_tag(earliest_tag)?.tag_datetime().date();
             let earlier_date = approx_date - chrono::Days::new(ddays);
             let earlier_tag = select_earlier_release(&self.releases, Some(earlier_date))?;
-            let earlier_release = self.releases.get_release(earlier_tag).clone();
+            let earlier_release = self.releases.get_release(earlier_tag)?;
             println!("found earlier release: {:?}", earlier_release.tag_name);
-            return self.activate_release(&earlier_release);
-        } else {
-            let earliest_bad_tag = &self.releases.find_tag(earliest_bad.unwrap()).clone();
-            let latest_good_tag = &self.releases.find_tag(latest_good.unwrap()).clone();
-            let mut midpoint_tag =
-                bisecting::select_midpoint_tag(&self.releases, latest_good_tag, earliest_bad_tag);
+            self.activate_release(&earlier_release)?;
+            return Ok(true);
+        };
+        let earliest_bad_tag = &self.releases.find_tag(&earliest_bad)?.clone();
+        let latest_good_tag = &self.releases.find_tag(&latest_good)?.clone();
+        let mut midpoint_tag =
+            bisecting::select_midpoint_tag(&self.releases, latest_good_tag, earliest_bad_tag);
+        if self.track.is_tag_skipped(midpoint_tag) {
+            log::warn!("midpoint would be {:?}, but it's skipped", midpoint_tag);
+            let old_mp = midpoint_tag;
+            midpoint_tag = bisecting::select_midpoint_tag(&self.releases, latest_good_tag, old_mp);
             if self.track.is_tag_skipped(midpoint_tag) {
-                log::warn!("midpoint would be {:?}, but it's skipped", midpoint_tag);
-                let old_mp = midpoint_tag;
+                log::warn!(
+                    "midpoint would be {:?}, but it's skipped AGAIN",
+                    midpoint_tag
+                );
                 midpoint_tag =
-                    bisecting::select_midpoint_tag(&self.releases, latest_good_tag, old_mp);
-                if self.track.is_tag_skipped(midpoint_tag) {
-                    log::warn!(
-                        "midpoint would be {:?}, but it's skipped AGAIN",
-                        midpoint_tag
-                    );
-                    midpoint_tag =
-                        bisecting::select_midpoint_tag(&self.releases, old_mp, earliest_bad_tag);
-                }
+                    bisecting::select_midpoint_tag(&self.releases, old_mp, earliest_bad_tag);
             }
-            assert!(!self.track.is_tag_skipped(midpoint_tag));
-            if midpoint_tag == earliest_bad_tag || midpoint_tag == latest_good_tag {
-                // self.releases
-                //     .maybe_fetch_releases(&[latest_good_tag, &earliest_bad_tag]);
-                let good_rel = self.releases.get_release(latest_good_tag);
-                let bad_rel = self.releases.get_release(earliest_bad_tag);
+        }
+        assert!(!self.track.is_tag_skipped(midpoint_tag));
+        if midpoint_tag == earliest_bad_tag || midpoint_tag == latest_good_tag {
+            // self.releases
+            //     .maybe_fetch_releases(&[latest_good_tag, &earliest_bad_tag]);
+            let good_rel = self.releases.get_release(latest_good_tag)?;
+            let bad_rel = self.releases.get_release(earliest_bad_tag)?;
+            println!(
+                "Bisected to commit range ( {} , {} ]\n  latest good - [{}]({})\n  earliest bad - [{}]({})",
+                &good_rel.target_commitish, &bad_rel.target_commitish,
+                good_rel.tag_name, good_rel.html_url, bad_rel.tag_name, bad_rel.html_url
+            );
+            return Ok(false);
+        }
+
+        println!(
+            "Approx. {} steps left.",
+            bisecting::get_steps_left(&self.releases, latest_good_tag, earliest_bad_tag)
+        );
+
+        let release = self.releases.get_release(midpoint_tag)?;
+        self.activate_release(&release)?;
+        Ok(true)
+    }
+
+    fn advance_mods(&mut self) -> anyhow::Result<bool> {
+        let userdata_dir = self.userdata_dir()?;
+        let mod_bisect = self.mod_bisect.as_mut().unwrap();
+        let more = mod_bisect.next(&userdata_dir)?;
+        if more {
+            println!("Trying mods {:?}", mod_bisect.testing().unwrap());
+        } else {
+            println!("Minimal failing mod set: {:?}", mod_bisect.failing());
+        }
+        Ok(more)
+    }
+    /// The active tag and where its files are
+    fn active_build_dir(&self) -> anyhow::Result<(String, PathBuf)> {
+        let (release, asset) = self
+            .active_install
+            .as_ref()
+            .with_context(|| anyhow::format_err!("no active install"))?;
+        let build_dir = match self.config.partial_fetch {
+            Some(_) => partial_unpack_dir(&release.tag_name),
+            None => asset_unpack_dir(&self.config, asset),
+        };
+        Ok((release.tag_name.clone(), build_dir))
+    }
+    /// Marks the active build with the configured oracle's verdict.
+    fn auto_judge(&mut self) -> anyhow::Result<()> {
+        let oracle = self
+            .config
+            .oracle
+            .clone()
+            .with_context(|| anyhow::format_err!("no `oracle` in the config"))?;
+        let (tag, build_dir) = self.active_build_dir()?;
+        let userdata_dir = match oracle.uses_userdata() {
+            true => Some(self.prepare_userdata()?),
+            false => None,
+        };
+        let verdict = oracle.judge(&oracle::Candidate {
+            build_dir: &build_dir,
+            tag: &tag,
+            bounds: self.track.bounds(&self.releases.tags_list),
+            userdata_dir: userdata_dir.as_deref(),
+            game_executable: self.config.game_executable.as_deref(),
+        })?;
+        println!("Oracle says {:?}", verdict);
+        self.mark(verdict, Some("oracle"))
+    }
+    /// Takes measurements of the active build for the metric oracle, without marking it.
+    fn measure(&mut self, repeats: Option<&str>) -> anyhow::Result<()> {
+        let Some(oracle::OracleConfig::Metric(metric)) = &self.config.oracle else {
+            anyhow::bail!("the configured `oracle` isn't a metric one");
+        };
+        let repeats = match repeats {
+            Some(n) => n.parse()?,
+            None => metric.repeats,
+        };
+        let (tag, build_dir) = self.active_build_dir()?;
+        let executable = self.config.game_executable.as_deref();
+        let values = metric.measure.measure(&build_dir, executable, repeats)?;
+        let mut all = metrics::Metrics::load()?;
+        all.record(&tag, &values)?;
+        println!(
+            "{}: median {:.3} over {} measurements",
+            tag,
+            all.median(&tag).unwrap(),
+            all.samples[&tag].len()
+        );
+        Ok(())
+    }
+    fn show_metrics(&self) -> anyhow::Result<()> {
+        let marks = |tag: &str| {
+            self.track
+                .0
+                .iter()
+                .rev()
+                .find(|e| e.tag == tag)
+                .map(|e| e.goodness)
+        };
+        print!(
+            "{}",
+            metrics::Metrics::load()?.chart(&self.releases.tags_list, &marks)?
+        );
+        Ok(())
+    }
+    fn auto_bisect(&mut self, args: Option<&str>) -> anyhow::Result<()> {
+        while self.advance(args)? {
+            let (release, _asset) = self.active_install.as_ref().unwrap();
+            if self.mod_bisect.is_none() && self.track.0.iter().any(|e| e.tag == release.tag_name) {
                 println!(
-                    "Bisected to commit range ( {} , {} ]\n  latest good - [{}]({})\n  earliest bad - [{}]({})",
-                    &good_rel.target_commitish, &bad_rel.target_commitish,
-                    good_rel.tag_name, good_rel.html_url, bad_rel.tag_name, bad_rel.html_url
+                    "{} is already marked, can't get further automatically",
+                    release.tag_name
                 );
                 return Ok(());
             }
-
-            println!(
-                "Approx. {} steps left.",
-                bisecting::get_steps_left(&self.releases, latest_good_tag, earliest_bad_tag)
-            );
-
-            let release = self.releases.get_release(midpoint_tag).clone();
-            return self.activate_release(&release);
+            self.auto_judge()?;
         }
+        Ok(())
+    }
+    fn mods_command(&mut self, args: Option<&str>, world: Option<&str>) -> anyhow::Result<()> {
+        match args {
+            Some("start") => {
+                anyhow::ensure!(
+                    self.mod_bisect.is_none(),
+                    "already bisecting mods, `mods stop` first"
+                );
+                let world =
+                    world.with_context(|| anyhow::format_err!("usage: mods start <world>"))?;
+                self.mod_bisect = Some(modbisect::ModBisect::start(&self.userdata_dir()?, world)?);
+                println!(
+                    "Bisecting the mods of {:?}. `next`, `run` and `mark` now work on mod sets",
+                    world
+                );
+            }
+            Some("stop") => {
+                if let Some(mod_bisect) = self.mod_bisect.take() {
+                    mod_bisect.stop(&self.userdata_dir()?)?;
+                }
+                println!("Back to bisecting versions");
+            }
+            None => match &self.mod_bisect {
+                Some(mod_bisect) => {
+                    for (mods, goodness) in &mod_bisect.history {
+                        println!("{:?} - {:?}", mods, goodness);
+                    }
+                    println!("Testing: {:?}", mod_bisect.testing());
+                    println!("Smallest failing set: {:?}", mod_bisect.failing());
+                }
+                None => println!("Not bisecting mods"),
+            },
+            _ => println!("?"),
+        }
+        Ok(())
     }
 
     fn find_freshest_install_tag(&self) -> Option<&GitTag> {
@@ -399,8 +1014,11 @@ impl BisectState {
                     .map(|c| c[1].to_string())
             })
             .max();
-        let freshest_tag =
-            freshest_date.map(|x| self.releases.find_tag(&format!("cdda-experimental-{}", x)));
+        let freshest_tag = freshest_date.and_then(|x| {
+            self.releases
+                .find_tag(&format!("cdda-experimental-{}", x))
+                .ok()
+        });
         log::debug!(
             "freshest = {:?} ; dir was {:?}",
             freshest_tag,
@@ -427,12 +1045,11 @@ impl BisectState {
                 .releases
                 .tags_list
                 .iter()
-                .filter(|r| r.name.contains(&want_tag_name))
-                .last();
+                .rfind(|r| r.name.contains(want_tag_name));
         }
         if let Some(tag_name) = tag_name {
-            let release = self.releases.get_release(tag_name);
-            self.activate_release(&release.clone())?;
+            let release = self.releases.get_release(tag_name)?;
+            self.activate_release(&release)?;
         } else {
             anyhow::bail!("Couldn't find tag {:?}", tag_name);
         }
@@ -443,56 +1060,544 @@ impl BisectState {
         self.track.save()?;
         Ok(())
     }
-    fn fix_font(&self) -> anyhow::Result<()> {
-        std::fs::remove_file(self.config.userdata_dir.join("config").join("fonts.json")).ok();
+    /// `profile [name|default]`
+    fn profile(&mut self, args: Option<&str>) -> anyhow::Result<()> {
+        let Some(name) = args else {
+            let current = self.release_profile.as_deref().unwrap_or("default");
+            println!("Release profile: {}", current);
+            let names = self.config.release_profiles.keys().collect::<Vec<_>>();
+            println!("Profiles: {:?}", names);
+            return Ok(());
+        };
+        let profile = (name != "default").then_some(name);
+        // the marks only make sense on the timeline they were made on
+        anyhow::ensure!(
+            self.track.0.is_empty(),
+            "the track is of the current profile's releases, `reset` before switching"
+        );
+        let sources = self.config.release_sources(profile)?;
+        self.releases = ReleaseHub::load(&sources).context("grabbing releases")?;
+        self.release_profile = profile.map(|p| p.to_string());
+        save_release_profile(profile)?;
+        println!("Releases now come from the {:?} profile", name);
+        Ok(())
+    }
+    fn pin_save(&mut self, world: Option<&str>) -> anyhow::Result<()> {
+        let Some(world) = world else {
+            match &self.pinned_save {
+                Some(p) => println!("Pinned save: {}", p.world),
+                None => println!("No save pinned"),
+            }
+            println!("Worlds: {:?}", saves::list_worlds(&self.userdata_dir()?));
+            return Ok(());
+        };
+        self.pinned_save = Some(saves::PinnedSave::pin(&self.userdata_dir()?, world)?);
+        println!("Pinned {:?}, it will be restored before every run", world);
         Ok(())
     }
+    fn unpin_save(&mut self) -> anyhow::Result<()> {
+        self.pinned_save = None;
+        saves::PinnedSave::unpin()
+    }
+    /// Downloads and unpacks (or partially fetches) `tag` if needed, without activating it
+    fn ensure_unpacked(&mut self, tag: &str) -> anyhow::Result<PathBuf> {
+        let release = self.releases.get_release(self.releases.find_tag(tag)?)?;
+        let asset = select_best_asset(&self.config, &release);
+        match &self.config.partial_fetch {
+            Some(glob) => {
+                let dir = partial_unpack_dir(tag);
+                partial_zip::fetch(&asset.browser_download_url, glob, &dir)?;
+                Ok(dir)
+            }
+            None => {
+                self.activate_asset(asset)?;
+                cache::Usage::touch(tag, &asset.name)?;
+                Ok(asset_unpack_dir(&self.config, asset))
+            }
+        }
+    }
+    /// `datadiff [text|md] [file]`
+    fn datadiff(&mut self, format: Option<&str>, out_file: Option<&str>) -> anyhow::Result<()> {
+        let markdown = match format {
+            None | Some("text") => false,
+            Some("md" | "markdown") => true,
+            Some(other) => anyhow::bail!("unknown format {:?}, expected `text` or `md`", other),
+        };
+        let (Some(latest_good), Some(earliest_bad)) = self.track.bounds(&self.releases.tags_list)
+        else {
+            anyhow::bail!("need at least one good and one bad mark to diff anything");
+        };
+        let good_dir = self.ensure_unpacked(&latest_good)?;
+        let bad_dir = self.ensure_unpacked(&earliest_bad)?;
+        let text = datadiff::diff(&good_dir, &bad_dir)?.render(markdown)?;
+        match out_file {
+            Some(path) => {
+                std::fs::File::create(path)?.write_all(text.as_bytes())?;
+                println!("Data diff written to {}", path);
+            }
+            None => println!("{} -> {}\n{}", latest_good, earliest_bad, text),
+        }
+        Ok(())
+    }
+    /// `statsdiff <type> [<old tag> <new tag>]`, the bounds by default
+    fn statsdiff(&self, stats_type: Option<&str>, tags: Option<&str>) -> anyhow::Result<()> {
+        let stats_type = stats_type.with_context(|| {
+            anyhow::format_err!("usage: statsdiff <type> [<old tag> <new tag>]")
+        })?;
+        let (old, new) = match tags.map(|t| t.split_whitespace().collect::<Vec<_>>()) {
+            Some(tags) => match tags.as_slice() {
+                [old, new] => (old.to_string(), new.to_string()),
+                _ => anyhow::bail!("usage: statsdiff <type> [<old tag> <new tag>]"),
+            },
+            None => match self.track.bounds(&self.releases.tags_list) {
+                (Some(good), Some(bad)) => (good, bad),
+                _ => anyhow::bail!(
+                    "no bounds to diff yet, give two of the tags with cached {} stats: {:?}",
+                    stats_type,
+                    stats::cached_tags(stats_type)?
+                ),
+            },
+        };
+        print!("{}", stats::diff(stats_type, &old, &new)?);
+        Ok(())
+    }
+    /// `cache ls`, `cache gc [dry-run]`
+    fn cache_command(&self, args: Option<&str>, extra: Option<&str>) -> anyhow::Result<()> {
+        let builds = cache::scan(&self.config.distr_dir, &self.config.unpack_dir)?;
+        match args {
+            Some("ls") => {
+                println!(
+                    "{:40} {:>10} {:>10}  {:16}  marks",
+                    "tag", "archive", "unpacked", "last used"
+                );
+                for build in &builds {
+                    let size = |x: &Option<(PathBuf, u64)>| {
+                        x.as_ref().map_or("-".into(), |x| cache::human_size(x.1))
+                    };
+                    let marks = build.tag.as_ref().map_or(vec![], |tag| {
+                        self.track
+                            .0
+                            .iter()
+                            .filter(|e| &e.tag == tag)
+                            .map(|e| format!("{:?}", e.goodness))
+                            .collect()
+                    });
+                    println!(
+                        "{:40} {:>10} {:>10}  {:16}  {}",
+                        build.tag.as_deref().unwrap_or(&build.stem),
+                        size(&build.archive),
+                        size(&build.unpacked),
+                        build.last_used.format("%Y-%m-%d %H:%M"),
+                        marks.join(", ")
+                    );
+                }
+                println!("Total: {}", cache::human_size(cache::total_size(&builds)));
+                if self.config.dedup_builds {
+                    println!(
+                        "Unpacked builds share files through the store, which takes {} in total. \
+                         Their sizes above are only the files no other build has",
+                        cache::human_size(store::size()?)
+                    );
+                }
+                Ok(())
+            }
+            Some("gc") => {
+                let dry_run = match extra {
+                    None => false,
+                    Some("dry-run" | "--dry-run") => true,
+                    Some(other) => anyhow::bail!("unknown option {:?}", other),
+                };
+                // the bounds, and whatever is active
+                let (good, bad) = self.track.bounds(&self.releases.tags_list);
+                let mut protected = std::collections::BTreeSet::new();
+                for t in [good, bad].into_iter().flatten() {
+                    let Ok(tag) = self.releases.find_tag(&t) else {
+                        continue;
+                    };
+                    let release = self.releases.get_release(tag)?;
+                    protected.insert(cache::stem(&select_best_asset(&self.config, &release).name));
+                }
+                if let Some((_, asset)) = &self.active_install {
+                    protected.insert(cache::stem(&asset.name));
+                }
+                let plan = cache::plan_gc(&builds, &self.config.cache_policy, &protected);
+                let mut freed = 0;
+                for removal in &plan {
+                    let (what, path, size) = match removal {
+                        cache::Removal::Unpacked(p, s) => ("unpacked", p, s),
+                        cache::Removal::Archive(p, s) => ("archive", p, s),
+                    };
+                    println!(
+                        "{:8} {} ({})",
+                        what,
+                        path.display(),
+                        cache::human_size(*size)
+                    );
+                    freed += size;
+                }
+                if dry_run {
+                    println!("Would free {}", cache::human_size(freed));
+                    return Ok(());
+                }
+                cache::execute(&plan)?;
+                if self.config.dedup_builds {
+                    freed = store::prune(&self.config.unpack_dir)?
+                        + plan
+                            .iter()
+                            .filter_map(|r| match r {
+                                cache::Removal::Archive(_, size) => Some(size),
+                                cache::Removal::Unpacked(..) => None,
+                            })
+                            .sum::<u64>();
+                }
+                println!("Freed {}", cache::human_size(freed));
+                Ok(())
+            }
+            _ => anyhow::bail!("usage: cache ls | cache gc [dry-run]"),
+        }
+    }
+    /// `verify [repair]`
+    fn verify(&self, args: Option<&str>) -> anyhow::Result<()> {
+        let report = store::verify(&self.config.unpack_dir)?;
+        for hash in &report.corrupt_objects {
+            println!("Corrupt store object {}", hash);
+        }
+        for (dir, problems) in &report.modified_builds {
+            println!("{}:", dir.display());
+            for problem in problems {
+                println!("  {}", problem);
+            }
+        }
+        if report.corrupt_objects.is_empty() && report.modified_builds.is_empty() {
+            println!("Store and builds are intact");
+            return Ok(());
+        }
+        match args {
+            Some("repair") => {
+                store::repair(&report)?;
+                println!("Removed the affected builds, they'll be unpacked again when activated");
+            }
+            _ => println!("Use `verify repair` to remove the affected builds"),
+        }
+        Ok(())
+    }
+    /// `serve [addr]`
+    fn serve(&self, args: Option<&str>) -> anyhow::Result<()> {
+        let addr = args.unwrap_or("0.0.0.0:8734");
+        // listed afresh for the clients, but not from other mirrors, which may well be asking us
+        let sources = self
+            .config
+            .release_sources(self.release_profile.as_deref())?
+            .into_iter()
+            .filter(|s| !matches!(s, release_source::SourceConfig::Mirror { .. }))
+            .collect::<Vec<_>>();
+        let list_tags = move || {
+            let sources = release_source::Sources(sources.iter().map(|s| s.build()).collect());
+            match sources.tags() {
+                Ok(tags) => tags.into_iter().map(|t| t.name).collect(),
+                Err(e) => {
+                    log::warn!("mirror: {:?}", e);
+                    vec![]
+                }
+            }
+        };
+        let addr = mirror::serve(addr, &self.config.distr_dir, list_tags)
+            .with_context(|| anyhow::format_err!("serving on {}", addr))?;
+        println!(
+            "Serving {:?} on {}, until you quit. Point the others' `mirrors` at http://<this machine>:{}",
+            self.config.distr_dir,
+            addr,
+            addr.port()
+        );
+        Ok(())
+    }
+    /// `add-local <path> --date <datetime> [--sha <commit>]`
+    fn add_local(&mut self, args: Option<&str>) -> anyhow::Result<()> {
+        let usage = "usage: add-local <path> --date <datetime> [--sha <commit>]";
+        // the path is everything up to the first flag, as it may well have spaces in it
+        let args = args.unwrap_or("");
+        let (path, flags) = args.split_at(args.find(" --").unwrap_or(args.len()));
+        let path = path.trim().trim_matches('"');
+        anyhow::ensure!(!path.is_empty(), usage);
+        let mut words = flags.split_whitespace();
+        let (mut date, mut sha) = (None, None);
+        while let Some(flag) = words.next() {
+            let value = words.next();
+            match (flag, value) {
+                ("--date", Some(v)) => date = Some(local_builds::parse_datetime(v)?),
+                ("--sha", Some(v)) => sha = Some(v.to_string()),
+                _ => anyhow::bail!(usage),
+            }
+        }
+        let date = date.with_context(|| anyhow::format_err!(usage))?;
+        let tag = self
+            .releases
+            .add_local(std::path::Path::new(path), date, sha)?;
+        println!("Registered {} as {}", path, tag.name);
+        Ok(())
+    }
+    fn report(&self, out_file: Option<&str>) -> anyhow::Result<()> {
+        let text = report::render(self)?;
+        match out_file {
+            Some(path) => {
+                std::fs::File::create(path)?.write_all(text.as_bytes())?;
+                println!("Report written to {}", path);
+            }
+            None => println!("{}", text),
+        }
+        Ok(())
+    }
+    fn apply_fixups(&self) -> anyhow::Result<()> {
+        let (release, _asset) = self
+            .active_install
+            .as_ref()
+            .with_context(|| anyhow::format_err!("no active install"))?;
+        let date = self
+            .releases
+            .find_tag(&release.tag_name)?
+            .tag_datetime()
+            .date();
+        let rules = fixups::builtin_rules()
+            .into_iter()
+            .chain(self.config.fixups.iter().cloned())
+            .collect::<Vec<_>>();
+        fixups::apply_all(&rules, &self.userdata_dir()?, date)
+    }
+    /// The userdir the active version runs with
+    fn userdata_dir(&self) -> anyhow::Result<PathBuf> {
+        if !self.config.isolate_userdata {
+            return Ok(self.config.userdata_dir.clone());
+        }
+        let (release, _asset) = self
+            .active_install
+            .as_ref()
+            .with_context(|| anyhow::format_err!("no active install"))?;
+        Ok(isolated_userdata_dir(&release.tag_name))
+    }
+    fn import_userdata(&self, source: Option<&str>) -> anyhow::Result<()> {
+        let source =
+            source.with_context(|| anyhow::format_err!("usage: import-userdata <path>"))?;
+        let shipped = self
+            .active_install
+            .as_ref()
+            .map(|(_, asset)| asset_unpack_dir(&self.config, asset));
+        if shipped.is_none() {
+            log::warn!("no active install, bundled tilesets can't be told apart from the user's");
+        }
+        let target = self.config.userdata_template_dir();
+        userdata_import::import(source, target, shipped.as_deref())?;
+        println!("Imported into {}", target.to_string_lossy());
+        Ok(())
+    }
+    fn userdir_command(&self, args: Option<&str>, tag: Option<&str>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.config.isolate_userdata,
+            "`isolate_userdata` is off in the config"
+        );
+        let dir = match tag {
+            Some(tag) => isolated_userdata_dir(tag),
+            None => self.userdata_dir()?,
+        };
+        match args {
+            Some("diff") => {
+                for change in fsutil::diff_trees(self.config.userdata_template_dir(), &dir)? {
+                    match change {
+                        fsutil::TreeChange::Added(f) => println!("+ {}", f.to_string_lossy()),
+                        fsutil::TreeChange::Removed(f) => println!("- {}", f.to_string_lossy()),
+                        fsutil::TreeChange::Changed(f) => println!("M {}", f.to_string_lossy()),
+                    }
+                }
+            }
+            Some("reset") => {
+                if dir.exists() {
+                    std::fs::remove_dir_all(&dir)?;
+                }
+                println!(
+                    "{:?} will be recreated from the template on the next run",
+                    dir
+                );
+            }
+            _ => println!("?"),
+        }
+        Ok(())
+    }
+}
+fn ask(question: &str) -> bool {
+    println!("{} [y/N]", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).unwrap();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Runs one command line. Returns `false` once it's time to quit.
+fn execute(bisect_state: &mut BisectState, line: &str) -> anyhow::Result<bool> {
+    let mut it = line.trim().splitn(2, " ");
+    let verb = it.next().unwrap();
+    let rest = it.next().map(|x| x.trim());
+    let mut it = rest.unwrap_or("").splitn(2, " ");
+    let args = it.next().filter(|x| !x.is_empty());
+    let extra = it.next().map(|x| x.trim());
+    let out = match verb {
+        "fetch" => bisect_state.fetch_more_releases(),
+        "launch" | "run" => bisect_state.launch(),
+        "mark" => match args {
+            Some("good") => bisect_state.mark(Goodness::Good, extra),
+            Some("bad") => bisect_state.mark(Goodness::Bad, extra),
+            Some("skip") => bisect_state.mark(Goodness::Skip, extra),
+            Some("blacklist") => bisect_state.mark_blacklist(extra),
+            _ => {
+                println!("?");
+                Ok(())
+            }
+        },
+        "next" | "advance" => bisect_state.advance(args).map(|_| ()),
+        "auto" => bisect_state.auto_judge(),
+        "auto-bisect" => bisect_state.auto_bisect(args),
+        "measure" => bisect_state.measure(args),
+        "metrics" => bisect_state.show_metrics(),
+        "track" => bisect_state.show_track(),
+        "runs" => bisect_state.show_runs(args),
+        "report" => bisect_state.report(args),
+        "datadiff" => bisect_state.datadiff(args, extra),
+        "statsdiff" => bisect_state.statsdiff(args, extra),
+        "cache" => bisect_state.cache_command(args, extra),
+        "verify" => bisect_state.verify(args),
+        "add-local" => bisect_state.add_local(rest),
+        "serve" => bisect_state.serve(args),
+        "profile" => bisect_state.profile(args),
+        "activate" => bisect_state.activate_tag(args.unwrap()),
+        "quit" | "exit" => {
+            if let Err(e) = bisect_state.revert_overlays() {
+                println!("Error: {:?}", e);
+            }
+            return Ok(false);
+        }
+        "reset" => bisect_state.reset(),
+        "import-userdata" => bisect_state.import_userdata(rest),
+        "mods" => bisect_state.mods_command(args, extra),
+        "userdir" => bisect_state.userdir_command(args, extra),
+        "pin-save" => bisect_state.pin_save(rest),
+        "unpin-save" => bisect_state.unpin_save(),
+        "fixup" | "fix_font" | "fix-font" => bisect_state.apply_fixups(),
+        _ => {
+            println!("?");
+            Ok(())
+        }
+    };
+    out.map(|()| true)
 }
+
 fn interact() {
     let mut bisect_state = BisectState::new().unwrap();
     loop {
         println!("> ");
         let mut prompt: String = "".into();
         std::io::stdin().read_line(&mut prompt).unwrap();
-        let mut it = prompt.trim().split(" ");
-        let verb = it.next().unwrap();
-        let args = it.next().map(|x| x.trim());
-        let out = match verb {
-            "fetch" => bisect_state.fetch_more_releases(),
-            "launch" | "run" => bisect_state.launch(),
-            "mark" => match args {
-                Some("good") => bisect_state.mark_good(),
-                Some("bad") => bisect_state.mark_bad(),
-                Some("skip") => bisect_state.mark_skip(),
-                Some("blacklist") => bisect_state.mark_blacklist(),
-                _ => {
-                    println!("?");
-                    Ok(())
-                }
-            },
-            "next" | "advance" => bisect_state.advance(args),
-            "track" => bisect_state.show_track(),
-            "activate" => bisect_state.activate_tag(args.unwrap()),
-            "quit" | "exit" => break,
-            "reset" => bisect_state.reset(),
-            "fix_font" | "fix-font" => bisect_state.fix_font(),
-            _ => {
-                println!("?");
-                Ok(())
-            }
-        };
-        match out {
-            Ok(()) => {}
+        match execute(&mut bisect_state, &prompt) {
+            Ok(true) => {}
+            Ok(false) => break,
             Err(e) => println!("Error: {:?}", e),
         }
     }
 }
 
 fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("cdda_bisector=debug"))
-        .init();
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("cdda_bisector=debug"),
+    )
+    .init();
 
     println!("Hello, world!");
     interact();
     // get_all_releases(false).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(name: &str, datetime: &str) -> GitTag {
+        GitTag {
+            name: name.into(),
+            datetime: Some(datetime.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn bounds_follow_the_timeline_not_tag_names() {
+        let timeline = vec![
+            at("fork-nightly-117", "2024-03-02T00:00:00"),
+            GitTag::named("cdda-experimental-2024-03-01-0000"),
+            at("local-mine", "2024-02-15T00:00:00"),
+            at("fork-nightly-99", "2024-02-10T00:00:00"),
+            GitTag::named("cdda-experimental-2024-02-01-0000"),
+        ];
+        let track = |marks: &[(&str, Goodness)]| {
+            Track(
+                marks
+                    .iter()
+                    .map(|&(t, g)| TrackEntry::new(t.into(), g))
+                    .collect(),
+            )
+        };
+
+        let marks = track(&[
+            ("cdda-experimental-2024-02-01-0000", Goodness::Good),
+            ("local-mine", Goodness::Good),
+            ("cdda-experimental-2024-03-01-0000", Goodness::Bad),
+        ]);
+        assert_eq!(
+            marks.bounds(&timeline),
+            (
+                Some("local-mine".into()),
+                Some("cdda-experimental-2024-03-01-0000".into())
+            )
+        );
+        let marks = track(&[
+            ("cdda-experimental-2024-02-01-0000", Goodness::Good),
+            ("cdda-experimental-2024-03-01-0000", Goodness::Bad),
+            ("local-mine", Goodness::Bad),
+        ]);
+        assert_eq!(marks.bounds(&timeline).1, Some("local-mine".into()));
+        assert_eq!(
+            marks.bounds_after(2, &timeline).1,
+            Some("cdda-experimental-2024-03-01-0000".into())
+        );
+        let marks = track(&[
+            ("fork-nightly-99", Goodness::Good),
+            ("fork-nightly-117", Goodness::Bad),
+        ]);
+        assert_eq!(
+            marks.bounds(&timeline),
+            (
+                Some("fork-nightly-99".into()),
+                Some("fork-nightly-117".into())
+            )
+        );
+    }
+
+    #[test]
+    fn release_profiles_pick_the_sources() {
+        let config: Config = json5::from_str(
+            r#"{
+                distr_dir: "distr", unpack_dir: "unpack", userdata_dir: "user", zip_extractor_path: "7z",
+                release_sources: [{kind: "git"}],
+                release_profiles: {
+                    ourfork: [{kind: "forgejo", url: "https://git.example.org", repo: "ourfork/CDDA"}],
+                },
+            }"#,
+        )
+        .unwrap();
+        let kinds = |profile| {
+            config
+                .release_sources(profile)
+                .unwrap()
+                .iter()
+                .map(|s| format!("{:?}", s).split(' ').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(None), vec!["Git"]);
+        assert_eq!(kinds(Some("ourfork")), vec!["Forgejo"]);
+        assert!(config.release_sources(Some("upstream")).is_err());
+    }
+}
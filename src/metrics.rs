@@ -0,0 +1,332 @@
+//! Bisecting on a number rather than a verdict: load times, memory use, anything a script prints.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::release_hub::GitTag;
+use crate::Goodness;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct MetricOracle {
+    pub measure: Measure,
+    /// measurements per tag, their median is what's compared
+    #[serde(default = "default_repeats")]
+    pub repeats: usize,
+    /// values past this are bad. Without it, the midpoint between the good and bad bounds' values is used,
+    /// which homes in on the largest step between them
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    /// for metrics like fps, where a drop is the regression
+    #[serde(default)]
+    pub lower_is_bad: bool,
+}
+
+fn default_repeats() -> usize {
+    3
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Measure {
+    /// seconds the build takes to run with `args`, e.g. `["--dump-stats", "ITEM"]` to time loading the data
+    Runtime { args: Vec<String> },
+    /// peak resident memory, in MiB, of the build running with `args`. Linux only
+    PeakRss { args: Vec<String> },
+    /// the last number printed by `command`, which gets the build dir appended as its last argument
+    Script { command: Vec<String> },
+}
+
+fn game_command(
+    build_dir: &Path,
+    executable: Option<&str>,
+    args: &[String],
+) -> anyhow::Result<std::process::Command> {
+    let mut cmd = crate::oracle::headless_game(build_dir, executable)?;
+    cmd.args(args)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    Ok(cmd)
+}
+
+fn vm_hwm_kib(pid: u32) -> Option<f64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+impl Measure {
+    fn measure_once(&self, build_dir: &Path, executable: Option<&str>) -> anyhow::Result<f64> {
+        match self {
+            Measure::Runtime { args } => {
+                let start = Instant::now();
+                let status = game_command(build_dir, executable, args)?.status()?;
+                anyhow::ensure!(
+                    status.success(),
+                    "game {}",
+                    crate::crash::describe_exit(&status)
+                );
+                Ok(start.elapsed().as_secs_f64())
+            }
+            Measure::PeakRss { args } => {
+                anyhow::ensure!(cfg!(target_os = "linux"), "peak_rss only works on linux");
+                let exe = crate::oracle::game_executable(build_dir, executable)?;
+                anyhow::ensure!(
+                    exe.extension().is_none_or(|e| e != "exe"),
+                    "peak_rss needs a native linux build, not {:?}. Put a linux asset into `preferred_assets`",
+                    exe
+                );
+                let mut child = game_command(build_dir, executable, args)?.spawn()?;
+                // the high water mark only grows, so the last reading before exit is the peak
+                let mut peak = 0.0f64;
+                let status = loop {
+                    if let Some(kib) = vm_hwm_kib(child.id()) {
+                        peak = peak.max(kib);
+                    }
+                    if let Some(status) = child.try_wait()? {
+                        break status;
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                };
+                anyhow::ensure!(
+                    status.success(),
+                    "game {}",
+                    crate::crash::describe_exit(&status)
+                );
+                Ok(peak / 1024.0)
+            }
+            Measure::Script { command } => {
+                let (program, args) = command
+                    .split_first()
+                    .with_context(|| anyhow::format_err!("empty metric `command`"))?;
+                let output = std::process::Command::new(program)
+                    .args(args)
+                    .arg(build_dir)
+                    .output()?;
+                anyhow::ensure!(
+                    output.status.success(),
+                    "metric script {}",
+                    crate::crash::describe_exit(&output.status)
+                );
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let number = regex::Regex::new(r"-?\d+(\.\d+)?([eE][-+]?\d+)?").unwrap();
+                let last = number
+                    .find_iter(&stdout)
+                    .last()
+                    .with_context(|| anyhow::format_err!("metric script printed no number"))?;
+                Ok(last.as_str().parse()?)
+            }
+        }
+    }
+
+    /// `executable` is the configured `game_executable`, if any
+    pub fn measure(
+        &self,
+        build_dir: &Path,
+        executable: Option<&str>,
+        repeats: usize,
+    ) -> anyhow::Result<Vec<f64>> {
+        let mut out = vec![];
+        for i in 0..repeats.max(1) {
+            let value = self.measure_once(build_dir, executable)?;
+            log::info!("measurement {}/{}: {}", i + 1, repeats.max(1), value);
+            out.push(value);
+        }
+        Ok(out)
+    }
+}
+
+pub fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((sorted[mid - 1] + sorted[mid]) / 2.0),
+        _ => Some(sorted[mid]),
+    }
+}
+
+/// A tag and its median
+type Point<'a> = (&'a str, f64);
+
+/// Every measurement taken so far, by tag
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct Metrics {
+    pub samples: BTreeMap<String, Vec<f64>>,
+}
+
+fn metrics_file() -> PathBuf {
+    Path::new("cache/metrics.json").into()
+}
+
+impl Metrics {
+    pub fn load() -> anyhow::Result<Self> {
+        let file = metrics_file();
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(file)?)?)
+    }
+    fn save(&self) -> anyhow::Result<()> {
+        std::fs::File::create(metrics_file())?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+    pub fn record(&mut self, tag: &str, values: &[f64]) -> anyhow::Result<()> {
+        self.samples
+            .entry(tag.to_string())
+            .or_default()
+            .extend(values);
+        self.save()
+    }
+    pub fn median(&self, tag: &str) -> Option<f64> {
+        median(self.samples.get(tag)?)
+    }
+
+    /// Medians of all measured tags, in release order. `tags` is the timeline, for the dates of local builds
+    fn series(&self, tags: &[GitTag]) -> Vec<Point<'_>> {
+        let mut out = self
+            .samples
+            .iter()
+            .filter_map(|(tag, v)| Some((tag.as_str(), median(v)?)))
+            .collect::<Vec<_>>();
+        out.sort_by_key(|(tag, _)| {
+            let date = match tags.iter().find(|t| t.name == *tag) {
+                Some(t) => t.try_tag_datetime(),
+                None => GitTag {
+                    name: tag.to_string(),
+                    datetime: None,
+                }
+                .try_tag_datetime(),
+            };
+            (date, tag.to_string())
+        });
+        out
+    }
+
+    /// Adjacent measured tags with the biggest difference between them
+    fn largest_step(&self, tags: &[GitTag]) -> Option<(Point<'_>, Point<'_>)> {
+        self.series(tags)
+            .windows(2)
+            .map(|w| (w[0], w[1]))
+            .max_by(|a, b| (a.1 .1 - a.0 .1).abs().total_cmp(&(b.1 .1 - b.0 .1).abs()))
+    }
+
+    pub fn chart(
+        &self,
+        tags: &[GitTag],
+        marks: &dyn Fn(&str) -> Option<Goodness>,
+    ) -> anyhow::Result<String> {
+        const WIDTH: f64 = 50.0;
+        let series = self.series(tags);
+        let mut out = String::new();
+        if series.is_empty() {
+            writeln!(out, "Nothing measured yet")?;
+            return Ok(out);
+        }
+        let min = series.iter().map(|s| s.1).fold(f64::INFINITY, f64::min);
+        let max = series.iter().map(|s| s.1).fold(f64::NEG_INFINITY, f64::max);
+        let tag_width = series.iter().map(|s| s.0.len()).max().unwrap_or(0);
+        for (tag, value) in &series {
+            // the axis starts at the minimum so that small changes are visible
+            let len = match max > min {
+                true => 1 + ((value - min) / (max - min) * (WIDTH - 1.0)).round() as usize,
+                false => WIDTH as usize,
+            };
+            let samples = &self.samples[*tag];
+            let spread = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+                - samples.iter().copied().fold(f64::INFINITY, f64::min);
+            let mark = match marks(tag) {
+                Some(Goodness::Good) => "G",
+                Some(Goodness::Bad) => "B",
+                Some(Goodness::Skip) => "S",
+                None => " ",
+            };
+            writeln!(
+                out,
+                "{:tag_width$} {} |{:<width$}| {:.3} (±{:.3}, n={})",
+                tag,
+                mark,
+                "#".repeat(len),
+                value,
+                spread / 2.0,
+                samples.len(),
+                width = WIDTH as usize,
+            )?;
+        }
+        if let Some(((from_tag, from), (to_tag, to))) = self.largest_step(tags) {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "Largest step: {} -> {}: {:.3} -> {:.3} ({:+.3})",
+                from_tag,
+                to_tag,
+                from,
+                to,
+                to - from
+            )?;
+        }
+        Ok(out)
+    }
+}
+
+impl MetricOracle {
+    /// The configured threshold, or the midpoint between the bounds' medians
+    fn threshold(
+        &self,
+        metrics: &Metrics,
+        bounds: &(Option<String>, Option<String>),
+    ) -> anyhow::Result<f64> {
+        if let Some(t) = self.threshold {
+            return Ok(t);
+        }
+        let (Some(good), Some(bad)) = bounds else {
+            anyhow::bail!("no `threshold` configured, need a good and a bad mark to derive one");
+        };
+        let value = |tag: &str| {
+            metrics.median(tag).with_context(|| {
+                anyhow::format_err!(
+                    "no `threshold` configured and {} hasn't been measured, `activate` and `measure` it",
+                    tag
+                )
+            })
+        };
+        Ok((value(good)? + value(bad)?) / 2.0)
+    }
+
+    /// Measures the build, records the values under `tag` and compares them to the threshold.
+    /// Builds that fail to run are skipped.
+    pub fn judge(
+        &self,
+        build_dir: &Path,
+        executable: Option<&str>,
+        tag: &str,
+        bounds: &(Option<String>, Option<String>),
+    ) -> anyhow::Result<Goodness> {
+        let mut metrics = Metrics::load()?;
+        let threshold = self.threshold(&metrics, bounds)?;
+        let values = match self.measure.measure(build_dir, executable, self.repeats) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("measuring {} failed: {:?}", tag, e);
+                return Ok(Goodness::Skip);
+            }
+        };
+        metrics.record(tag, &values)?;
+        let value = median(&values).unwrap();
+        println!("{}: {:.3} (threshold {:.3})", tag, value, threshold);
+        let past = match self.lower_is_bad {
+            false => value > threshold,
+            true => value < threshold,
+        };
+        Ok(match past {
+            true => Goodness::Bad,
+            false => Goodness::Good,
+        })
+    }
+}
@@ -0,0 +1,397 @@
+//! Sharing downloaded builds over the LAN: `serve` exposes `distr_dir` and the cached release metadata over HTTP,
+//! and `mirrors` in the config makes clients ask such servers before GitHub.
+//!
+//! A mirror serves `/index.json` (a [`MirrorIndex`]), `/releases/<tag>.json` (the GitHub release, as cached)
+//! and `/assets/<name>`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use sha2::Digest;
+
+use crate::release_hub::{GitTag, GithubRelease, ReleaseAsset};
+use crate::release_source::{AssetStream, ReleaseSource};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+pub struct MirrorIndex {
+    /// release tags the serving instance knows of, newest first
+    pub tags: Vec<String>,
+    /// tags whose release metadata can be fetched from `/releases/<tag>.json`
+    pub releases: Vec<String>,
+    pub assets: Vec<MirrorAsset>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct MirrorAsset {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Upstream's release metadata, with other sources' in subdirs
+pub fn release_cache_dir() -> PathBuf {
+    Path::new("cache/release_info").into()
+}
+
+/// Release metadata fetched before into `dir`, one of the `release_cache_dir`s
+pub fn cached_release(dir: &Path, tag: &str) -> Option<GithubRelease> {
+    let file = dir.join(format!("{}.json", tag));
+    serde_json::from_str(&std::fs::read_to_string(file).ok()?).ok()
+}
+
+pub fn cache_release(dir: &Path, release: &GithubRelease) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::File::create(dir.join(format!("{}.json", release.tag_name)))?
+        .write_all(serde_json::to_string_pretty(release)?.as_bytes())?;
+    Ok(())
+}
+
+fn cached_release_tags() -> anyhow::Result<Vec<String>> {
+    let mut out = vec![];
+    if !release_cache_dir().exists() {
+        return Ok(out);
+    }
+    for entry in std::fs::read_dir(release_cache_dir())? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(tag) = name.strip_suffix(".json") {
+            out.push(tag.to_string());
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Hashes of the served archives, redone only when a file's size or mtime changes
+type HashMemo = HashMap<String, (u64, std::time::SystemTime, String)>;
+
+/// How long the listed tags are served before they're listed again
+const TAGS_TTL: std::time::Duration = std::time::Duration::from_secs(600);
+
+type ListTags = Box<dyn Fn() -> Vec<String> + Send + Sync>;
+
+struct Server {
+    distr_dir: PathBuf,
+    list_tags: ListTags,
+    tags: Mutex<Option<(std::time::Instant, Vec<String>)>>,
+    hashes: Mutex<HashMemo>,
+}
+
+impl Server {
+    fn tags(&self) -> Vec<String> {
+        let mut tags = self.tags.lock().unwrap();
+        match &*tags {
+            Some((listed, tags)) if listed.elapsed() < TAGS_TTL => tags.clone(),
+            _ => {
+                let fresh = (self.list_tags)();
+                *tags = Some((std::time::Instant::now(), fresh.clone()));
+                fresh
+            }
+        }
+    }
+
+    fn assets(&self) -> anyhow::Result<Vec<MirrorAsset>> {
+        let mut out = vec![];
+        for entry in std::fs::read_dir(&self.distr_dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // skip downloads in progress
+            if !meta.is_file() || name.ends_with(".part") {
+                continue;
+            }
+            let modified = meta.modified()?;
+            let known = self.hashes.lock().unwrap().get(&name).cloned();
+            let sha256 = match known {
+                Some((size, time, hash)) if size == meta.len() && time == modified => hash,
+                _ => {
+                    let hash = crate::store::hash_file(&entry.path())?;
+                    self.hashes
+                        .lock()
+                        .unwrap()
+                        .insert(name.clone(), (meta.len(), modified, hash.clone()));
+                    hash
+                }
+            };
+            out.push(MirrorAsset {
+                name,
+                size: meta.len(),
+                sha256,
+            });
+        }
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    fn index(&self) -> anyhow::Result<MirrorIndex> {
+        let releases = cached_release_tags()?;
+        let listed = self.tags();
+        let mut tags = listed.clone();
+        tags.extend(releases.iter().filter(|t| !listed.contains(t)).cloned());
+        Ok(MirrorIndex {
+            tags,
+            releases,
+            assets: self.assets()?,
+        })
+    }
+
+    fn handle(&self, stream: std::net::TcpStream) -> anyhow::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("");
+        let path = parts.next().unwrap_or("").split('?').next().unwrap();
+        log::debug!("mirror: {} {}", method, path);
+
+        let mut stream = stream;
+        let respond = |stream: &mut std::net::TcpStream, status: &str, len: u64| {
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status, len
+            )
+        };
+        if method != "GET" {
+            respond(&mut stream, "405 Method Not Allowed", 0)?;
+            return Ok(());
+        }
+        if path == "/index.json" {
+            let body = serde_json::to_vec(&self.index()?)?;
+            respond(&mut stream, "200 OK", body.len() as u64)?;
+            stream.write_all(&body)?;
+            return Ok(());
+        }
+        if let Some(tag) = path
+            .strip_prefix("/releases/")
+            .and_then(|p| p.strip_suffix(".json"))
+        {
+            // only ever tags listed in the index, so no `..` tricks either
+            let known = cached_release_tags()?.iter().any(|t| t == tag);
+            if let Some(release) = cached_release(&release_cache_dir(), tag).filter(|_| known) {
+                let body = serde_json::to_vec(&release)?;
+                respond(&mut stream, "200 OK", body.len() as u64)?;
+                stream.write_all(&body)?;
+                return Ok(());
+            }
+        }
+        if let Some(name) = path.strip_prefix("/assets/") {
+            // only ever files listed in the index, so no `..` tricks
+            if self.assets()?.iter().any(|a| a.name == name) {
+                let mut file = std::fs::File::open(self.distr_dir.join(name))?;
+                respond(&mut stream, "200 OK", file.metadata()?.len())?;
+                std::io::copy(&mut file, &mut stream)?;
+                return Ok(());
+            }
+        }
+        respond(&mut stream, "404 Not Found", 0)?;
+        Ok(())
+    }
+}
+
+/// Starts serving in the background. `list_tags` gives the releases to advertise on top of those with cached
+/// metadata, and is called again once what it gave is `TAGS_TTL` old.
+pub fn serve(
+    addr: &str,
+    distr_dir: &Path,
+    list_tags: impl Fn() -> Vec<String> + Send + Sync + 'static,
+) -> anyhow::Result<std::net::SocketAddr> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    let server = Arc::new(Server {
+        distr_dir: distr_dir.into(),
+        list_tags: Box::new(list_tags),
+        tags: Default::default(),
+        hashes: Default::default(),
+    });
+    log::info!("Hashing the archives in {:?}", distr_dir);
+    let assets = server.assets()?.len();
+    log::info!("..done, serving {} archives", assets);
+    let local_addr = listener.local_addr()?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let server = server.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = server.handle(stream) {
+                    log::warn!("mirror: {:?}", e);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+/// Another instance's `serve`, as a release source. Archives are checked against the hash in its index.
+pub struct MirrorSource {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl MirrorSource {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::builder()
+                .user_agent("moxian-bisector-thingy")
+                .timeout_connect(std::time::Duration::from_secs(3))
+                .build(),
+        }
+    }
+
+    fn index(&self) -> anyhow::Result<MirrorIndex> {
+        let body = self
+            .agent
+            .get(&format!("{}/index.json", self.url))
+            .call()?
+            .into_string()?;
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+
+impl ReleaseSource for MirrorSource {
+    fn describe(&self) -> String {
+        format!("mirror {}", self.url)
+    }
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+        Ok(Some(
+            self.index()?.tags.into_iter().map(GitTag::named).collect(),
+        ))
+    }
+    fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+        let response = match self
+            .agent
+            .get(&format!("{}/releases/{}.json", self.url, tag))
+            .call()
+        {
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            r => r?,
+        };
+        Ok(Some(serde_json::from_str(&response.into_string()?)?))
+    }
+    fn open_asset(&self, asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+        let Some(expected) = self
+            .index()?
+            .assets
+            .into_iter()
+            .find(|a| a.name == asset.name)
+        else {
+            return Ok(None);
+        };
+        let reader = self
+            .agent
+            .get(&format!("{}/assets/{}", self.url, asset.name))
+            .call()?
+            .into_reader();
+        Ok(Some(Box::new(Verified {
+            inner: reader,
+            hasher: sha2::Sha256::new(),
+            expected: expected.sha256,
+        })))
+    }
+}
+
+/// Fails at the end of the stream if the content doesn't match the hash
+struct Verified<R> {
+    inner: R,
+    hasher: sha2::Sha256,
+    expected: String,
+}
+
+impl<R: Read> Read for Verified<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.hasher.update(&buf[..n]);
+            return Ok(n);
+        }
+        let actual = format!("{:x}", self.hasher.clone().finalize());
+        if actual != self.expected {
+            return Err(std::io::Error::other(format!(
+                "sha256 mismatch: expected {}, got {}",
+                self.expected, actual
+            )));
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG: &str = "cdda-experimental-2025-01-01-0000";
+    const ASSET: &str = "cdda-windows-tiles-x64-2025-01-01-0000.zip";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mirror_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The status line of a request sent as is, with no normalization of the path
+    fn raw_get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\n\r\n", path).unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).unwrap();
+        status.trim().to_string()
+    }
+
+    #[test]
+    fn serves_tags_and_verified_archives() {
+        // releases are served from `cache/release_info` of the current dir
+        let _cwd = crate::TEST_CWD.lock().unwrap_or_else(|e| e.into_inner());
+        let distr_dir = temp_dir("serve");
+        std::fs::write(distr_dir.join(ASSET), b"the archive").unwrap();
+        std::fs::write(distr_dir.join("other.zip.part"), b"half").unwrap();
+        let listed = Arc::new(Mutex::new(0));
+        let counter = listed.clone();
+        let addr = serve("127.0.0.1:0", &distr_dir, move || {
+            *counter.lock().unwrap() += 1;
+            vec![TAG.to_string()]
+        })
+        .unwrap();
+        let source = MirrorSource::new(&format!("http://{}/", addr));
+
+        let tags = source.tags().unwrap().unwrap();
+        assert!(tags.iter().any(|t| t.name == TAG), "{:?}", tags);
+        source.tags().unwrap();
+        assert_eq!(
+            *listed.lock().unwrap(),
+            1,
+            "tags are listed once per TAGS_TTL"
+        );
+
+        let asset = |name: &str| ReleaseAsset {
+            name: name.into(),
+            browser_download_url: String::new(),
+        };
+        let mut body = vec![];
+        source
+            .open_asset(&asset(ASSET))
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"the archive");
+        assert!(source
+            .open_asset(&asset("other.zip.part"))
+            .unwrap()
+            .is_none());
+        assert!(source.release("no-such-tag").unwrap().is_none());
+        assert_eq!(
+            raw_get(addr, "/assets/../Cargo.toml"),
+            "HTTP/1.1 404 Not Found"
+        );
+        std::fs::remove_dir_all(distr_dir).unwrap();
+    }
+}
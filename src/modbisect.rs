@@ -0,0 +1,246 @@
+//! Delta debugging (ddmin) over a world's mod list, to find a minimal set of mods that triggers a bug.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::Goodness;
+
+/// never left out of a tested subset
+const CORE_MODS: &[&str] = &["dda"];
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ModBisect {
+    pub world: String,
+    /// the world's mod list before we started, restored on `stop`
+    original: Vec<String>,
+    /// smallest set of (non-core) mods known to trigger the bug
+    failing: Vec<String>,
+    /// how many chunks `failing` is split into
+    n: usize,
+    /// index into the subsets (`< n`), then into their complements (`>= n`)
+    step: usize,
+    testing: Option<Vec<String>>,
+    pub history: Vec<(Vec<String>, Goodness)>,
+    pub done: bool,
+}
+
+fn state_file() -> PathBuf {
+    Path::new("cache/modbisect.json").into()
+}
+
+fn mods_file(userdata_dir: &Path, world: &str) -> PathBuf {
+    userdata_dir.join("save").join(world).join("mods.json")
+}
+
+fn chunks(mods: &[String], n: usize) -> Vec<Vec<String>> {
+    let mut out = vec![];
+    let mut start = 0;
+    for i in 0..n {
+        let end = mods.len() * (i + 1) / n;
+        out.push(mods[start..end].to_vec());
+        start = end;
+    }
+    out
+}
+
+impl ModBisect {
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let file = state_file();
+        if !file.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(file)?)?))
+    }
+    fn save(&self) -> anyhow::Result<()> {
+        std::fs::File::create(state_file())?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Starts from the world's current mod list, which is assumed to reproduce the bug.
+    pub fn start(userdata_dir: &Path, world: &str) -> anyhow::Result<Self> {
+        let original: Vec<String> =
+            serde_json::from_str(&std::fs::read_to_string(mods_file(userdata_dir, world))?)?;
+        let failing = original
+            .iter()
+            .filter(|m| !CORE_MODS.contains(&m.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        anyhow::ensure!(!failing.is_empty(), "{:?} has no non-core mods", world);
+        let out = Self {
+            world: world.to_string(),
+            original,
+            n: 2.min(failing.len()),
+            done: failing.len() == 1,
+            failing,
+            step: 0,
+            testing: None,
+            history: vec![],
+        };
+        out.save()?;
+        Ok(out)
+    }
+
+    /// Puts the original mod list back and forgets the session.
+    pub fn stop(self, userdata_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(
+            mods_file(userdata_dir, &self.world),
+            serde_json::to_string_pretty(&self.original)?,
+        )?;
+        std::fs::remove_file(state_file())?;
+        Ok(())
+    }
+
+    fn candidate(&self) -> Option<Vec<String>> {
+        let parts = chunks(&self.failing, self.n);
+        if self.step < self.n {
+            return Some(parts[self.step].clone());
+        }
+        // with two parts the complements are just the subsets again
+        if self.n == 2 || self.step >= 2 * self.n {
+            return None;
+        }
+        let skip = self.step - self.n;
+        let complement = parts
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != skip)
+            .flat_map(|(_, p)| p.iter().cloned())
+            .collect();
+        Some(complement)
+    }
+
+    /// Picks the next subset to try and writes it into the world. Returns false once minimal.
+    pub fn next(&mut self, userdata_dir: &Path) -> anyhow::Result<bool> {
+        while !self.done && self.testing.is_none() {
+            match self.candidate() {
+                Some(c) => self.testing = Some(c),
+                None if self.n < self.failing.len() => {
+                    self.n = (self.n * 2).min(self.failing.len());
+                    self.step = 0;
+                }
+                None => self.done = true,
+            }
+        }
+        self.save()?;
+        if self.done {
+            return Ok(false);
+        }
+        self.write_mods(userdata_dir)?;
+        Ok(true)
+    }
+
+    pub fn write_mods(&self, userdata_dir: &Path) -> anyhow::Result<()> {
+        let Some(testing) = &self.testing else {
+            return Ok(());
+        };
+        let mods = self
+            .original
+            .iter()
+            .filter(|m| CORE_MODS.contains(&m.as_str()) || testing.contains(m))
+            .collect::<Vec<_>>();
+        std::fs::write(
+            mods_file(userdata_dir, &self.world),
+            serde_json::to_string_pretty(&mods)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn testing(&self) -> Option<&[String]> {
+        self.testing.as_deref()
+    }
+    pub fn failing(&self) -> &[String] {
+        &self.failing
+    }
+
+    /// Skips count as "doesn't reproduce", as usual for ddmin.
+    pub fn mark(&mut self, goodness: Goodness) -> anyhow::Result<()> {
+        let testing = self
+            .testing
+            .take()
+            .ok_or_else(|| anyhow::format_err!("nothing is being tested, use `next`"))?;
+        self.history.push((testing.clone(), goodness));
+        if goodness == Goodness::Bad {
+            let was_subset = self.step < self.n;
+            self.failing = testing;
+            self.n = if was_subset { 2 } else { (self.n - 1).max(2) };
+            self.n = self.n.min(self.failing.len());
+            self.step = 0;
+            if self.failing.len() == 1 {
+                self.done = true;
+            }
+        } else {
+            self.step += 1;
+        }
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddmin_finds_a_planted_pair() {
+        let _cwd = crate::TEST_CWD.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("modbisect_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let userdata = dir.join("userdata");
+        std::fs::create_dir_all(userdata.join("save/World")).unwrap();
+        std::fs::create_dir_all(dir.join("cache")).unwrap();
+        let original = ["dda", "a", "b", "c", "d", "e", "f", "g", "h"];
+        std::fs::write(
+            mods_file(&userdata, "World"),
+            serde_json::to_string(&original).unwrap(),
+        )
+        .unwrap();
+        let old = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+
+        let result = std::panic::catch_unwind(|| {
+            let mut session = ModBisect::start(&userdata, "World").unwrap();
+            let mut tried = vec![];
+            while session.next(&userdata).unwrap() {
+                let written: Vec<String> = serde_json::from_str(
+                    &std::fs::read_to_string(mods_file(&userdata, "World")).unwrap(),
+                )
+                .unwrap();
+                assert_eq!(written[0], "dda", "core mods are always in");
+                let testing = session.testing().unwrap().join("");
+                // only the two together break the game
+                let buggy = testing.contains('c') && testing.contains('f');
+                session
+                    .mark(if buggy { Goodness::Bad } else { Goodness::Good })
+                    .unwrap();
+                tried.push(format!("{}{}", testing, if buggy { "!" } else { "" }));
+            }
+            assert!(session.done);
+            let failing = session.failing().to_vec();
+            session.stop(&userdata).unwrap();
+            (failing, tried)
+        });
+        std::env::set_current_dir(old).unwrap();
+        let (failing, tried) = result.unwrap();
+
+        #[rustfmt::skip]
+        let expected = vec![
+            // two halves, neither fails alone
+            "abcd", "efgh",
+            // n grows to 4: subsets, then complements until one fails
+            "ab", "cd", "ef", "gh", "cdefgh!",
+            // n shrinks to max(4 - 1, 2) = 3
+            "cd", "ef", "gh", "efgh", "cdgh", "cdef!",
+            // down to 2, then up to min(2 * 2, 4) = 4
+            "cd", "ef", "c", "d", "e", "f", "def", "cef!",
+            // 3, then 2, where the halves are all there is to try
+            "c", "e", "f", "ef", "cf!", "c", "f",
+        ];
+        assert_eq!(tried, expected);
+        assert_eq!(failing, ["c", "f"]);
+        let restored: Vec<String> =
+            serde_json::from_str(&std::fs::read_to_string(mods_file(&userdata, "World")).unwrap())
+                .unwrap();
+        assert_eq!(restored, original);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! Automated good/bad verdicts for the active build, so `auto-bisect` can run unattended.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::Goodness;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OracleConfig {
+    /// inspects the build's json game data, without launching it
+    Data(DataOracle),
+    /// runs the build with `--check-mods`, for finding when a mod (usually an overlay) broke
+    CheckMods(CheckModsOracle),
+    /// measures a number and compares it to a threshold, for performance regressions
+    Metric(crate::metrics::MetricOracle),
+    /// plays a keystroke script against the curses build and checks the screen
+    Keystrokes(crate::pty_driver::KeystrokeOracle),
+    /// checks a cell of the build's `--dump-stats` table
+    DumpStats(crate::stats::StatsOracle),
+}
+
+/// What an oracle gets to look at
+pub struct Candidate<'a> {
+    pub build_dir: &'a Path,
+    pub tag: &'a str,
+    /// the current latest good and earliest bad tags
+    pub bounds: (Option<String>, Option<String>),
+    /// prepared for a run, for the oracles that launch the game with the user's data
+    pub userdata_dir: Option<&'a Path>,
+    /// `game_executable` from the config, for the oracles that launch the game headless
+    pub game_executable: Option<&'a str>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DataOracle {
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub id: String,
+    /// dot-separated path into the object, e.g. `melee_damage.bash` or `components.0.0`
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub predicate: Predicate,
+    /// the verdict for builds where the predicate holds; the opposite one is used otherwise
+    #[serde(default = "default_when_matching")]
+    pub when_matching: Goodness,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct CheckModsOracle {
+    #[serde(rename = "mod")]
+    pub mod_id: String,
+    /// errors matching this are the bug we're after; builds with only other errors are skipped
+    #[serde(default)]
+    pub interesting: Option<String>,
+}
+
+pub fn default_when_matching() -> Goodness {
+    Goodness::Bad
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum Predicate {
+    #[default]
+    Exists,
+    Missing,
+    Eq(serde_json::Value),
+    Ne(serde_json::Value),
+    Lt(f64),
+    Gt(f64),
+    Contains(serde_json::Value),
+}
+impl Predicate {
+    pub fn holds(&self, value: Option<&serde_json::Value>) -> bool {
+        match (self, value) {
+            (Predicate::Exists, v) => v.is_some(),
+            (Predicate::Missing, v) => v.is_none(),
+            (Predicate::Ne(x), v) => !v.is_some_and(|v| same_value(v, x)),
+            (_, None) => false,
+            (Predicate::Eq(x), Some(v)) => same_value(v, x),
+            (Predicate::Lt(x), Some(v)) => v.as_f64().is_some_and(|v| v < *x),
+            (Predicate::Gt(x), Some(v)) => v.as_f64().is_some_and(|v| v > *x),
+            (Predicate::Contains(x), Some(serde_json::Value::Array(items))) => items.contains(x),
+            (Predicate::Contains(x), Some(serde_json::Value::String(s))) => {
+                x.as_str().is_some_and(|x| s.contains(x))
+            }
+            (Predicate::Contains(_), Some(_)) => false,
+        }
+    }
+}
+
+/// Like `==`, except that numbers compare by value: serde_json has `10.0 != 10`
+fn same_value(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    match (a, b) {
+        (serde_json::Value::Number(a), serde_json::Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => a == b,
+    }
+}
+
+pub fn opposite(goodness: Goodness) -> Goodness {
+    match goodness {
+        Goodness::Good => Goodness::Bad,
+        Goodness::Bad => Goodness::Good,
+        Goodness::Skip => Goodness::Skip,
+    }
+}
+
+fn object_has_id(obj: &serde_json::Value, id: &str) -> bool {
+    // recipes are keyed by their result rather than an id
+    ["id", "abstract", "result"]
+        .iter()
+        .any(|key| match obj.get(key) {
+            Some(serde_json::Value::String(s)) => s == id,
+            Some(serde_json::Value::Array(ids)) => ids.iter().any(|i| i.as_str() == Some(id)),
+            _ => false,
+        })
+}
+
+fn lookup<'a>(obj: &'a serde_json::Value, path: Option<&str>) -> Option<&'a serde_json::Value> {
+    let Some(path) = path else {
+        return Some(obj);
+    };
+    path.split('.').try_fold(obj, |v, key| match v {
+        serde_json::Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => v.get(key),
+    })
+}
+
+/// All json objects of `object_type` with the given id in the build's `data/json` and `data/mods`
+pub fn find_objects(
+    data_dir: &Path,
+    object_type: &str,
+    id: &str,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut out = vec![];
+    for sub in ["json", "mods"] {
+        for file in crate::fsutil::list_files(&data_dir.join(sub))? {
+            if file.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let path = data_dir.join(sub).join(&file);
+            let Ok(parsed) = serde_json::from_slice::<serde_json::Value>(&std::fs::read(&path)?)
+            else {
+                log::debug!("can't parse {:?}", path);
+                continue;
+            };
+            let objects = match parsed {
+                serde_json::Value::Array(items) => items,
+                other => vec![other],
+            };
+            out.extend(objects.into_iter().filter(|o| {
+                o.get("type").and_then(|t| t.as_str()) == Some(object_type) && object_has_id(o, id)
+            }));
+        }
+    }
+    Ok(out)
+}
+
+/// The build's `data` dir: at the top, or in the one folder the archive wraps everything in
+/// (as partial fetches with a `*/data/json/**` glob have it)
+pub fn data_dir(build_dir: &Path) -> anyhow::Result<PathBuf> {
+    let mut candidates = vec![build_dir.join("data")];
+    if build_dir.is_dir() {
+        for entry in std::fs::read_dir(build_dir)? {
+            candidates.push(entry?.path().join("data"));
+        }
+    }
+    let found = candidates
+        .into_iter()
+        .filter(|d| d.join("json").is_dir())
+        .collect::<Vec<_>>();
+    match found.as_slice() {
+        [data] => Ok(data.clone()),
+        [] => anyhow::bail!("no data/json in {:?}, nothing to judge", build_dir),
+        _ => anyhow::bail!("more than one data/json in {:?}: {:?}", build_dir, found),
+    }
+}
+
+impl DataOracle {
+    pub fn judge(&self, build_dir: &Path) -> anyhow::Result<Goodness> {
+        let objects = find_objects(&data_dir(build_dir)?, &self.object_type, &self.id)?;
+        log::debug!(
+            "found {} {} {:?} objects",
+            objects.len(),
+            self.object_type,
+            self.id
+        );
+        let values = objects
+            .iter()
+            .filter_map(|o| lookup(o, self.path.as_deref()))
+            .collect::<Vec<_>>();
+        let holds = match values.as_slice() {
+            [] => self.predicate.holds(None),
+            values => values.iter().any(|v| self.predicate.holds(Some(v))),
+        };
+        Ok(match holds {
+            true => self.when_matching,
+            false => opposite(self.when_matching),
+        })
+    }
+}
+
+/// `--userdir` for headless runs of the game, so the real userdir is left alone
+const SCRATCH_USERDIR: &str = "cache/scratch_userdir";
+
+/// Game binaries a build may have, the ones native to this platform first
+const GAME_EXECUTABLES: &[&str] = if cfg!(windows) {
+    &["cataclysm-tiles.exe", "cataclysm.exe"]
+} else {
+    &[
+        "cataclysm-tiles",
+        "cataclysm",
+        "cataclysm-tiles.exe",
+        "cataclysm.exe",
+    ]
+};
+
+/// The build's game binary, whichever of the windows and linux ones it has
+pub fn find_game_executable(build_dir: &Path) -> anyhow::Result<PathBuf> {
+    GAME_EXECUTABLES
+        .iter()
+        .map(|name| build_dir.join(name))
+        .find(|exe| exe.is_file())
+        .with_context(|| anyhow::format_err!("no game executable in {:?}", build_dir))
+}
+
+/// The configured game binary of the build, or else whichever one it has
+pub fn game_executable(build_dir: &Path, configured: Option<&str>) -> anyhow::Result<PathBuf> {
+    let Some(name) = configured else {
+        return find_game_executable(build_dir);
+    };
+    let exe = build_dir.join(name);
+    anyhow::ensure!(exe.is_file(), "no {:?} in {:?}", name, build_dir);
+    Ok(exe)
+}
+
+/// The build's game binary, set up to run without a window and without touching the real userdir
+pub fn headless_game(
+    build_dir: &Path,
+    executable: Option<&str>,
+) -> anyhow::Result<std::process::Command> {
+    let exe = game_executable(build_dir, executable)?;
+    std::fs::create_dir_all(SCRATCH_USERDIR)?;
+    let mut cmd = std::process::Command::new(exe);
+    cmd.args(["--basepath", &build_dir.to_string_lossy()])
+        .args(["--userdir", SCRATCH_USERDIR]);
+    Ok(cmd)
+}
+
+impl CheckModsOracle {
+    pub fn judge(&self, build_dir: &Path, executable: Option<&str>) -> anyhow::Result<Goodness> {
+        let interesting = self
+            .interesting
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()?;
+        log::info!("Checking mod {:?}", self.mod_id);
+        let output = headless_game(build_dir, executable)?
+            .args(["--check-mods", &self.mod_id])
+            .output()
+            .context("running --check-mods")?;
+        let text =
+            String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+        let errors = text
+            .lines()
+            .filter(|l| l.to_lowercase().contains("error"))
+            .collect::<Vec<_>>();
+        for line in errors.iter().take(10) {
+            println!("  {}", line);
+        }
+        if output.status.success() && errors.is_empty() {
+            return Ok(Goodness::Good);
+        }
+        log::info!(
+            "--check-mods failed ({}), {} error lines",
+            crate::crash::describe_exit(&output.status),
+            errors.len()
+        );
+        Ok(match interesting {
+            Some(re) if !errors.iter().any(|l| re.is_match(l)) => Goodness::Skip,
+            _ => Goodness::Bad,
+        })
+    }
+}
+
+impl OracleConfig {
+    /// Whether `judge` needs `Candidate::userdata_dir`
+    pub fn uses_userdata(&self) -> bool {
+        matches!(self, OracleConfig::Keystrokes(_))
+    }
+
+    pub fn judge(&self, candidate: &Candidate) -> anyhow::Result<Goodness> {
+        let (build_dir, executable) = (candidate.build_dir, candidate.game_executable);
+        match self {
+            OracleConfig::Data(data) => data.judge(build_dir),
+            OracleConfig::CheckMods(check) => check.judge(build_dir, executable),
+            OracleConfig::Metric(metric) => {
+                metric.judge(build_dir, executable, candidate.tag, &candidate.bounds)
+            }
+            OracleConfig::DumpStats(stats) => stats.judge(build_dir, executable, candidate.tag),
+            OracleConfig::Keystrokes(keys) => {
+                let userdata_dir = candidate
+                    .userdata_dir
+                    .with_context(|| anyhow::format_err!("no userdir prepared"))?;
+                let run_dir = crate::runs::new_run_dir(candidate.tag, &chrono::Local::now())?;
+                let verdict = keys.judge(build_dir, userdata_dir, &run_dir)?;
+                println!("Final screen saved to {}", run_dir.to_string_lossy());
+                Ok(verdict)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir_is_found_or_missed_loudly() {
+        let root = std::env::temp_dir().join(format!("oracle_data_dir_{}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        let partial = root.join("partial");
+        std::fs::create_dir_all(partial.join("cdda/data/json")).unwrap();
+        assert_eq!(data_dir(&partial).unwrap(), partial.join("cdda/data"));
+
+        let full = root.join("full");
+        std::fs::create_dir_all(full.join("data/json")).unwrap();
+        assert_eq!(data_dir(&full).unwrap(), full.join("data"));
+
+        // a fetch that got no data at all is no evidence either way
+        std::fs::create_dir_all(root.join("empty/cdda/gfx")).unwrap();
+        assert!(data_dir(&root.join("empty")).is_err());
+        assert!(data_dir(&root.join("missing")).is_err());
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use sha2::Digest;
+
+use crate::fsutil::copy_dir_all;
+
+/// Third-party content copied into every activated build
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Overlay {
+    /// a directory, or an archive the zip extractor can open
+    pub source: PathBuf,
+    pub kind: OverlayKind,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayKind {
+    Mod,
+    Tileset,
+    Soundpack,
+}
+impl OverlayKind {
+    fn target_dir(self) -> &'static Path {
+        match self {
+            OverlayKind::Mod => Path::new("data/mods"),
+            OverlayKind::Tileset => Path::new("gfx"),
+            OverlayKind::Soundpack => Path::new("data/sound"),
+        }
+    }
+}
+
+/// What was put into a build, so it can be taken out again
+pub const MANIFEST: &str = ".bisector_overlays.json";
+
+/// The directory to copy for `overlay`, unpacking archives into `cache/overlays/` first
+fn overlay_content(overlay: &Overlay, zip_extractor: &Path) -> anyhow::Result<PathBuf> {
+    if overlay.source.is_dir() {
+        return Ok(overlay.source.clone());
+    }
+    anyhow::ensure!(
+        overlay.source.is_file(),
+        "overlay {:?} not found",
+        overlay.source
+    );
+    let stem = overlay
+        .source
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+    // archives with the same name in different places are different overlays, and a changed archive is a new one
+    let meta = overlay.source.metadata()?;
+    let key = format!(
+        "{:?} {} {:?}",
+        std::fs::canonicalize(&overlay.source)?,
+        meta.len(),
+        meta.modified()?
+    );
+    let hash = format!("{:x}", sha2::Sha256::digest(key.as_bytes()));
+    let unpacked = Path::new("cache/overlays").join(format!("{}-{}", stem, &hash[..12]));
+    if !unpacked.exists() {
+        crate::unpack_archive(zip_extractor, &overlay.source, &unpacked)?;
+    }
+    // most archives wrap their content into a single folder
+    let children = std::fs::read_dir(&unpacked)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match children.as_slice() {
+        [only] if only.is_dir() => Ok(only.clone()),
+        _ => Ok(unpacked),
+    }
+}
+
+pub fn apply(build_dir: &Path, overlays: &[Overlay], zip_extractor: &Path) -> anyhow::Result<()> {
+    revert(build_dir)?;
+    let mut installed = vec![];
+    for overlay in overlays {
+        let content = overlay_content(overlay, zip_extractor)?;
+        let rel = overlay.kind.target_dir().join(content.file_name().unwrap());
+        let dest = build_dir.join(&rel);
+        if dest.exists() {
+            log::warn!("{:?} already exists in the build, not overlaying it", rel);
+            continue;
+        }
+        log::info!("Overlaying {:?} -> {:?}", content, dest);
+        copy_dir_all(&content, &dest)?;
+        installed.push(rel);
+    }
+    if !installed.is_empty() {
+        std::fs::File::create(build_dir.join(MANIFEST))?
+            .write_all(serde_json::to_string_pretty(&installed)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Paths relative to the build that `apply` put there
+pub fn installed(build_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let manifest = build_dir.join(MANIFEST);
+    if !manifest.exists() {
+        return Ok(vec![]);
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(&manifest)?)?)
+}
+
+/// Removes whatever `apply` put into the build, leaving the unpack pristine.
+pub fn revert(build_dir: &Path) -> anyhow::Result<()> {
+    let manifest = build_dir.join(MANIFEST);
+    if !manifest.exists() {
+        return Ok(());
+    }
+    for rel in installed(build_dir)? {
+        log::debug!("removing overlay {:?}", rel);
+        std::fs::remove_dir_all(build_dir.join(rel)).ok();
+    }
+    std::fs::remove_file(manifest)?;
+    Ok(())
+}
+
+/// Reverts every build under `unpack_dir` that still has overlays in it, e.g. after a crash or Ctrl-C
+pub fn revert_all(unpack_dir: &Path) -> anyhow::Result<()> {
+    let Ok(entries) = std::fs::read_dir(unpack_dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let build_dir = entry?.path();
+        if build_dir.join(MANIFEST).exists() {
+            log::info!("Removing overlays left in {:?}", build_dir);
+            revert(&build_dir)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,447 @@
+//! Fetches only some files out of a remote zip, using HTTP range requests.
+//! Good enough for data-only bisects, where the 100+MB of binaries and graphics aren't needed.
+
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Context;
+
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+/// end of central directory record plus the longest possible comment
+const EOCD_SEARCH_LEN: u64 = 22 + 0xffff;
+/// entries closer than this are fetched with a single request
+const MERGE_GAP: u64 = 256 * 1024;
+/// written into a complete partial unpack, holds the glob it was made with
+const MARKER: &str = ".partial_glob";
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    offset: u64,
+}
+
+/// `len` bytes at `i`, or an error if the archive is cut short there
+fn bytes_at(b: &[u8], i: usize, len: usize) -> anyhow::Result<&[u8]> {
+    b.get(i..i.saturating_add(len))
+        .with_context(|| anyhow::format_err!("truncated zip: no {} bytes at {}", len, i))
+}
+fn u16_at(b: &[u8], i: usize) -> anyhow::Result<u16> {
+    Ok(u16::from_le_bytes(bytes_at(b, i, 2)?.try_into()?))
+}
+fn u32_at(b: &[u8], i: usize) -> anyhow::Result<u32> {
+    Ok(u32::from_le_bytes(bytes_at(b, i, 4)?.try_into()?))
+}
+
+/// `*` matches within a path segment, `**` across them
+pub fn glob_regex(glob: &str) -> regex::Regex {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re += ".*";
+            }
+            '*' => re += "[^/]*",
+            '?' => re += "[^/]",
+            c => re += &regex::escape(&c.to_string()),
+        }
+    }
+    re += "$";
+    regex::Regex::new(&re).unwrap()
+}
+
+struct RemoteZip {
+    agent: ureq::Agent,
+    url: String,
+    len: u64,
+}
+impl RemoteZip {
+    fn open(url: &str) -> anyhow::Result<Self> {
+        let agent = ureq::builder().user_agent("moxian-bisector-thingy").build();
+        let response = agent.head(url).call()?;
+        let len = response
+            .header("Content-Length")
+            .and_then(|l| l.parse().ok())
+            .ok_or_else(|| anyhow::format_err!("no Content-Length for {}", url))?;
+        Ok(Self {
+            agent,
+            url: url.into(),
+            len,
+        })
+    }
+
+    /// bytes `[start, end)`
+    fn fetch(&self, start: u64, end: u64) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(
+            start < end && end <= self.len,
+            "bad range {}..{} of a {} byte zip",
+            start,
+            end,
+            self.len
+        );
+        log::debug!("fetching bytes {}..{} of {}", start, end, self.url);
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", start, end - 1))
+            .call()?;
+        anyhow::ensure!(
+            response.status() == 206,
+            "server doesn't support range requests (status {})",
+            response.status()
+        );
+        let mut buf = vec![];
+        response.into_reader().read_to_end(&mut buf)?;
+        anyhow::ensure!(buf.len() as u64 == end - start, "short range response");
+        Ok(buf)
+    }
+
+    /// All entries, sorted by their position in the archive
+    fn central_directory(&self) -> anyhow::Result<(Vec<Entry>, u64)> {
+        let tail_start = self.len.saturating_sub(EOCD_SEARCH_LEN);
+        let tail = self.fetch(tail_start, self.len)?;
+        let eocd = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|&i| u32_at(&tail, i).is_ok_and(|s| s == EOCD_SIGNATURE))
+            .ok_or_else(|| anyhow::format_err!("not a zip file"))?;
+        let cd_size = u32_at(&tail, eocd + 12)? as u64;
+        let cd_offset = u32_at(&tail, eocd + 16)? as u64;
+        anyhow::ensure!(
+            cd_offset != 0xffffffff && cd_size != 0xffffffff,
+            "zip64 archives aren't supported"
+        );
+        let cd = self.fetch(cd_offset, cd_offset + cd_size)?;
+
+        let mut entries = vec![];
+        let mut i = 0;
+        while i + 46 <= cd.len() && u32_at(&cd, i)? == CENTRAL_HEADER_SIGNATURE {
+            let name_len = u16_at(&cd, i + 28)? as usize;
+            let extra_len = u16_at(&cd, i + 30)? as usize;
+            let comment_len = u16_at(&cd, i + 32)? as usize;
+            entries.push(Entry {
+                name: String::from_utf8_lossy(bytes_at(&cd, i + 46, name_len)?).into(),
+                method: u16_at(&cd, i + 10)?,
+                crc: u32_at(&cd, i + 16)?,
+                compressed_size: u32_at(&cd, i + 20)? as u64,
+                offset: u32_at(&cd, i + 42)? as u64,
+            });
+            i += 46 + name_len + extra_len + comment_len;
+        }
+        entries.sort_by_key(|e| e.offset);
+        Ok((entries, cd_offset))
+    }
+}
+
+fn extract_entry(entry: &Entry, local: &[u8], out_dir: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        u32_at(local, 0)? == LOCAL_HEADER_SIGNATURE,
+        "bad local header for {}",
+        entry.name
+    );
+    let data_start = 30 + u16_at(local, 26)? as usize + u16_at(local, 28)? as usize;
+    let data = bytes_at(local, data_start, entry.compressed_size as usize)
+        .with_context(|| format!("reading {}", entry.name))?;
+    let content = match entry.method {
+        0 => data.to_vec(),
+        8 => {
+            let mut out = vec![];
+            flate2::read::DeflateDecoder::new(data).read_to_end(&mut out)?;
+            out
+        }
+        m => anyhow::bail!("unsupported compression method {} for {}", m, entry.name),
+    };
+    let mut crc = flate2::Crc::new();
+    crc.update(&content);
+    anyhow::ensure!(crc.sum() == entry.crc, "crc mismatch for {}", entry.name);
+
+    let path = out_dir.join(
+        entry_path(&entry.name).with_context(|| format!("suspicious entry {:?}", entry.name))?,
+    );
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Where entry `name` goes under the output dir, unless it would end up elsewhere:
+/// absolute, with a drive letter or `..`, with either kind of slash
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(name.replace('\\', "/"));
+    let normal = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(p) if !p.to_string_lossy().contains(':')));
+    (normal && path.components().next().is_some()).then_some(path)
+}
+
+/// Unpacks the entries of the zip at `url` matching `glob` into `out_dir`.
+/// Does nothing if `out_dir` already holds a complete fetch for the same glob.
+pub fn fetch(url: &str, glob: &str, out_dir: &Path) -> anyhow::Result<()> {
+    if std::fs::read_to_string(out_dir.join(MARKER)).is_ok_and(|g| g == glob) {
+        return Ok(());
+    }
+    let zip = RemoteZip::open(url)?;
+    let (entries, cd_offset) = zip.central_directory()?;
+    let re = glob_regex(glob);
+
+    // an entry ends no later than where the next one starts
+    let mut spans: Vec<(u64, u64, Vec<&Entry>)> = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.name.ends_with('/') || !re.is_match(&entry.name) {
+            continue;
+        }
+        anyhow::ensure!(
+            entry_path(&entry.name).is_some(),
+            "suspicious entry {:?}",
+            entry.name
+        );
+        let end = entries.get(i + 1).map(|e| e.offset).unwrap_or(cd_offset);
+        match spans.last_mut() {
+            Some(span) if entry.offset <= span.1 + MERGE_GAP => {
+                span.1 = end;
+                span.2.push(entry);
+            }
+            _ => spans.push((entry.offset, end, vec![entry])),
+        }
+    }
+    log::info!(
+        "Fetching {} files in {} requests, {} of {} bytes",
+        spans.iter().map(|s| s.2.len()).sum::<usize>(),
+        spans.len(),
+        spans.iter().map(|s| s.1.saturating_sub(s.0)).sum::<u64>(),
+        zip.len
+    );
+
+    if out_dir.exists() {
+        std::fs::remove_dir_all(out_dir)?;
+    }
+    for (start, end, span_entries) in spans {
+        let buf = zip.fetch(start, end)?;
+        for entry in span_entries {
+            let local = buf
+                .get((entry.offset - start) as usize..)
+                .with_context(|| format!("{} is outside of its span", entry.name))?;
+            extract_entry(entry, local, out_dir)?;
+        }
+    }
+    std::fs::create_dir_all(out_dir)?;
+    std::fs::write(out_dir.join(MARKER), glob)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A zip with the given files, every other one deflated
+    fn make_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = vec![];
+        let mut central = vec![];
+        for (i, (name, content)) in files.iter().enumerate() {
+            let (method, data) = if i % 2 == 0 {
+                (0u16, content.to_vec())
+            } else {
+                let mut enc =
+                    flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+                enc.write_all(content).unwrap();
+                (8u16, enc.finish().unwrap())
+            };
+            let mut crc = flate2::Crc::new();
+            crc.update(content);
+            let offset = out.len() as u32;
+
+            out.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            out.extend([20, 0, 0, 0]);
+            out.extend(method.to_le_bytes());
+            out.extend([0; 4]);
+            out.extend(crc.sum().to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((content.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend([0, 0]);
+            out.extend(name.as_bytes());
+            out.extend(&data);
+
+            central.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 4]);
+            central.extend(crc.sum().to_le_bytes());
+            central.extend((data.len() as u32).to_le_bytes());
+            central.extend((content.len() as u32).to_le_bytes());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+        let cd_offset = out.len() as u32;
+        out.extend(&central);
+        out.extend(EOCD_SIGNATURE.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((central.len() as u32).to_le_bytes());
+        out.extend(cd_offset.to_le_bytes());
+        out.extend([0, 0]);
+        out
+    }
+
+    #[test]
+    fn glob() {
+        let re = glob_regex("*/data/json/**");
+        assert!(re.is_match("cdda/data/json/items/tool.json"));
+        assert!(!re.is_match("cdda/gfx/data/json/a.json"));
+        assert!(!re.is_match("data/json/a.json"));
+    }
+
+    #[test]
+    fn fetches_only_matching_entries() {
+        let big = vec![b'x'; 2 * MERGE_GAP as usize];
+        let zip = make_zip(&[
+            ("cdda/data/json/a.json", b"[{\"id\": \"a\"}]"),
+            ("cdda/data/json/sub/b.json", b"[{\"id\": \"b\"}]"),
+            ("cdda/gfx/huge.png", &big),
+            ("cdda/data/json/c.json", b"[]"),
+            ("cdda/cataclysm-tiles.exe", b"MZ"),
+        ]);
+        let zip_len = zip.len();
+        let server = crate::test_server::serve(
+            [("/build.zip".to_string(), (200, zip))]
+                .into_iter()
+                .collect(),
+        );
+
+        let out_dir = std::env::temp_dir().join(format!("partial_zip_test_{}", std::process::id()));
+        fetch(
+            &format!("{}/build.zip", server.url),
+            "*/data/json/**",
+            &out_dir,
+        )
+        .unwrap();
+
+        let files = crate::fsutil::list_files(&out_dir).unwrap();
+        let files = files
+            .iter()
+            .map(|f| f.to_string_lossy().replace('\\', "/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                ".partial_glob",
+                "cdda/data/json/a.json",
+                "cdda/data/json/c.json",
+                "cdda/data/json/sub/b.json"
+            ]
+        );
+        assert_eq!(
+            std::fs::read(out_dir.join("cdda/data/json/sub/b.json")).unwrap(),
+            b"[{\"id\": \"b\"}]"
+        );
+
+        let requests = server.requests.lock().unwrap().clone();
+        let fetched: usize = requests
+            .iter()
+            .filter_map(|r| r.split("bytes=").nth(1))
+            .map(|r| {
+                let (s, e) = r.split_once('-').unwrap();
+                e.parse::<usize>().unwrap() + 1 - s.parse::<usize>().unwrap()
+            })
+            .sum();
+        assert!(fetched < zip_len / 2, "{:?}", requests);
+
+        // a second fetch with the same glob is a no-op
+        let before = requests.len();
+        fetch(
+            &format!("{}/build.zip", server.url),
+            "*/data/json/**",
+            &out_dir,
+        )
+        .unwrap();
+        assert_eq!(server.requests.lock().unwrap().len(), before);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn entries_stay_inside_the_output_dir() {
+        assert_eq!(
+            entry_path("cdda\\data\\json\\a.json"),
+            Some(PathBuf::from("cdda/data/json/a.json"))
+        );
+        for hostile in [
+            "/etc/x",
+            "C:\\x",
+            "C:x",
+            "..\\x",
+            "cdda/../../x",
+            "\\\\server\\x",
+            "",
+        ] {
+            assert_eq!(entry_path(hostile), None, "{:?}", hostile);
+        }
+
+        let zip = make_zip(&[("cdda/data/a.json", b"[]"), ("..\\x.json", b"[]")]);
+        let server = crate::test_server::serve(
+            [("/build.zip".to_string(), (200, zip))]
+                .into_iter()
+                .collect(),
+        );
+        let out = std::env::temp_dir().join(format!("partial_zip_hostile_{}", std::process::id()));
+        let err = fetch(&format!("{}/build.zip", server.url), "**", &out).unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("suspicious entry"),
+            "{:?}",
+            err
+        );
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn malformed_archives_are_errors() {
+        let zip = make_zip(&[("cdda/data/json/a.json", b"[]"), ("cdda/x.exe", b"MZ")]);
+        let cd_offset =
+            zip.len() - 22 - 2 * 46 - "cdda/data/json/a.json".len() - "cdda/x.exe".len();
+        // a name running past the end of the central directory
+        let mut long_name = zip.clone();
+        long_name[cd_offset + 28..cd_offset + 30].copy_from_slice(&0xfff0u16.to_le_bytes());
+        // an entry claiming to be bigger than the archive
+        let mut big_entry = zip.clone();
+        big_entry[cd_offset + 20..cd_offset + 24].copy_from_slice(&0x00ffffffu32.to_le_bytes());
+        // a central directory past the end
+        let mut bad_offset = zip.clone();
+        let eocd = bad_offset.len() - 22;
+        bad_offset[eocd + 16..eocd + 20].copy_from_slice(&0x7fffffffu32.to_le_bytes());
+
+        let server = crate::test_server::serve(
+            [
+                ("/long_name.zip".to_string(), (200, long_name)),
+                ("/big_entry.zip".to_string(), (200, big_entry)),
+                ("/bad_offset.zip".to_string(), (200, bad_offset)),
+                (
+                    "/truncated.zip".to_string(),
+                    (200, zip[..zip.len() / 2].to_vec()),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        for name in ["long_name", "big_entry", "bad_offset", "truncated"] {
+            let out_dir = std::env::temp_dir().join(format!(
+                "partial_zip_malformed_{}_{}",
+                name,
+                std::process::id()
+            ));
+            let result = fetch(
+                &format!("{}/{}.zip", server.url, name),
+                "*/data/json/**",
+                &out_dir,
+            );
+            assert!(result.is_err(), "{}", name);
+            std::fs::remove_dir_all(out_dir).ok();
+        }
+    }
+}
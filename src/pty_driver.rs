@@ -0,0 +1,262 @@
+//! Plays a keystroke script against the curses build in a pseudo-terminal, and judges it by what's on screen.
+//!
+//! Scripts are plain text, one step per line, lines starting with `#` are comments:
+//!
+//! ```text
+//! # seconds the following `wait`s may take (default 60)
+//! timeout 120
+//! # until the text is on screen
+//! wait Press any key
+//! # keys, separated by spaces: a single character, or one of the names in `key_bytes`
+//! send space
+//! # literal text
+//! type hello world
+//! sleep 1.5
+//! # the build is bad unless this is on screen right now
+//! expect You see here
+//! expect-not Segmentation fault
+//! ```
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::Goodness;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct KeystrokeOracle {
+    pub script: PathBuf,
+    /// the curses binary, relative to the build dir
+    #[serde(default = "default_executable")]
+    pub executable: String,
+    #[serde(default = "default_rows")]
+    pub rows: u16,
+    #[serde(default = "default_cols")]
+    pub cols: u16,
+}
+
+fn default_executable() -> String {
+    "cataclysm".into()
+}
+fn default_rows() -> u16 {
+    24
+}
+fn default_cols() -> u16 {
+    80
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Timeout(Duration),
+    Wait(String),
+    Send(Vec<u8>),
+    Sleep(Duration),
+    Expect(String),
+    ExpectNot(String),
+}
+
+fn key_bytes(key: &str) -> anyhow::Result<Vec<u8>> {
+    let named: &[u8] = match key.to_lowercase().as_str() {
+        "enter" | "return" => b"\r",
+        "esc" | "escape" => b"\x1b",
+        "tab" => b"\t",
+        "backspace" => b"\x7f",
+        "space" => b" ",
+        "up" => b"\x1b[A",
+        "down" => b"\x1b[B",
+        "right" => b"\x1b[C",
+        "left" => b"\x1b[D",
+        "home" => b"\x1b[H",
+        "end" => b"\x1b[F",
+        "pgup" => b"\x1b[5~",
+        "pgdn" => b"\x1b[6~",
+        "f1" => b"\x1bOP",
+        "f2" => b"\x1bOQ",
+        "f3" => b"\x1bOR",
+        "f4" => b"\x1bOS",
+        k => {
+            if let Some(c) = k.strip_prefix("ctrl-") {
+                let [c] = c.as_bytes() else {
+                    anyhow::bail!("unknown key {:?}", key);
+                };
+                return Ok(vec![c & 0x1f]);
+            }
+            anyhow::ensure!(key.chars().count() == 1, "unknown key {:?}", key);
+            return Ok(key.as_bytes().to_vec());
+        }
+    };
+    Ok(named.to_vec())
+}
+
+fn parse_script(text: &str) -> anyhow::Result<Vec<Step>> {
+    let mut out = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        let step = (|| {
+            anyhow::ensure!(!arg.is_empty(), "`{}` needs an argument", command);
+            Ok(match command {
+                "timeout" => Step::Timeout(Duration::from_secs_f64(arg.parse()?)),
+                "wait" => Step::Wait(arg.into()),
+                "send" => Step::Send(
+                    arg.split_whitespace()
+                        .map(key_bytes)
+                        .collect::<anyhow::Result<Vec<_>>>()?
+                        .concat(),
+                ),
+                "type" => Step::Send(arg.as_bytes().to_vec()),
+                "sleep" => Step::Sleep(Duration::from_secs_f64(arg.parse()?)),
+                "expect" => Step::Expect(arg.into()),
+                "expect-not" => Step::ExpectNot(arg.into()),
+                _ => anyhow::bail!("unknown command {:?}", command),
+            })
+        })()
+        .with_context(|| anyhow::format_err!("script line {}", n + 1))?;
+        out.push(step);
+    }
+    Ok(out)
+}
+
+struct Session {
+    screen: Arc<Mutex<vt100::Parser>>,
+    writer: Box<dyn Write + Send>,
+    child: Box<dyn portable_pty::Child + Send + Sync>,
+    // dropping it hangs up the terminal
+    _master: Box<dyn portable_pty::MasterPty + Send>,
+}
+
+impl Session {
+    fn start(
+        exe: &Path,
+        args: &[String],
+        cwd: &Path,
+        rows: u16,
+        cols: u16,
+    ) -> anyhow::Result<Self> {
+        let pair = portable_pty::native_pty_system().openpty(portable_pty::PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })?;
+        let mut cmd = portable_pty::CommandBuilder::new(exe);
+        cmd.args(args);
+        cmd.cwd(cwd);
+        cmd.env("TERM", "xterm-256color");
+        let child = pair.slave.spawn_command(cmd)?;
+        drop(pair.slave);
+
+        let screen = Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0)));
+        let mut reader = pair.master.try_clone_reader()?;
+        let feed = screen.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                feed.lock().unwrap().process(&buf[..n]);
+            }
+        });
+        Ok(Self {
+            screen,
+            writer: pair.master.take_writer()?,
+            child,
+            _master: pair.master,
+        })
+    }
+
+    fn contents(&self) -> String {
+        self.screen.lock().unwrap().screen().contents()
+    }
+
+    fn exited(&mut self) -> bool {
+        !matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl KeystrokeOracle {
+    fn run_steps(
+        &self,
+        steps: &[Step],
+        session: &mut Session,
+    ) -> anyhow::Result<Result<Goodness, String>> {
+        let mut timeout = Duration::from_secs(60);
+        for step in steps {
+            log::debug!("script: {:?}", step);
+            match step {
+                Step::Timeout(t) => timeout = *t,
+                Step::Wait(text) => {
+                    let start = Instant::now();
+                    while !session.contents().contains(text.as_str()) {
+                        if session.exited() {
+                            return Ok(Err("the game exited".into()));
+                        }
+                        if start.elapsed() > timeout {
+                            return Ok(Err(format!("timed out waiting for {:?}", text)));
+                        }
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Step::Send(bytes) => {
+                    session.writer.write_all(bytes)?;
+                    session.writer.flush()?;
+                }
+                Step::Sleep(t) => std::thread::sleep(*t),
+                Step::Expect(text) => {
+                    if !session.contents().contains(text.as_str()) {
+                        println!("Expected {:?} on screen, but it's not there", text);
+                        return Ok(Ok(Goodness::Bad));
+                    }
+                }
+                Step::ExpectNot(text) => {
+                    if session.contents().contains(text.as_str()) {
+                        println!("{:?} is on screen", text);
+                        return Ok(Ok(Goodness::Bad));
+                    }
+                }
+            }
+        }
+        Ok(Ok(Goodness::Good))
+    }
+
+    /// Builds that never get to the point the script checks (timeouts, early exits) are skipped.
+    /// The final screen is saved into `run_dir`.
+    pub fn judge(
+        &self,
+        build_dir: &Path,
+        userdata_dir: &Path,
+        run_dir: &Path,
+    ) -> anyhow::Result<Goodness> {
+        let steps = parse_script(
+            &std::fs::read_to_string(&self.script)
+                .with_context(|| anyhow::format_err!("reading {:?}", self.script))?,
+        )?;
+        let exe = build_dir.join(&self.executable);
+        anyhow::ensure!(exe.exists(), "no {:?} in {:?}", self.executable, build_dir);
+        let args = [
+            "--basepath".to_string(),
+            build_dir.to_string_lossy().to_string(),
+            "--userdir".to_string(),
+            userdata_dir.to_string_lossy().to_string(),
+        ];
+        let mut session = Session::start(&exe, &args, build_dir, self.rows, self.cols)?;
+        let outcome = self.run_steps(&steps, &mut session);
+        // give the last keys a moment to show up before taking the picture
+        std::thread::sleep(Duration::from_millis(200));
+        std::fs::write(run_dir.join("screen.txt"), session.contents())?;
+        session.child.kill().ok();
+        session.child.wait().ok();
+        Ok(match outcome? {
+            Ok(verdict) => verdict,
+            Err(stop) => {
+                println!("Script didn't finish ({}), skipping", stop);
+                Goodness::Skip
+            }
+        })
+    }
+}
@@ -1,21 +1,33 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::Write;
 
 use anyhow::Context;
 
+use crate::local_builds::LocalBuilds;
+use crate::release_source::{SourceConfig, Sources};
 use crate::ReleaseBlacklist;
 
-const GITHUB_API_ENDPOINT: &'static str = r"https://api.github.com";
-const CDDA_REPO: &'static str = r"CleverRaven/Cataclysm-DDA";
+pub const GITHUB_API_ENDPOINT: &str = r"https://api.github.com";
+pub const CDDA_REPO: &str = r"CleverRaven/Cataclysm-DDA";
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct GitTag {
     pub name: String,
-    // datetime: chrono::NaiveDateTime,
+    /// for pseudo-tags of local builds, whose name doesn't carry the date
+    pub datetime: Option<chrono::NaiveDateTime>,
 }
 impl GitTag {
+    /// A tag whose datetime comes from its name
+    pub fn named(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            datetime: None,
+        }
+    }
     pub fn try_tag_datetime(&self) -> Option<chrono::NaiveDateTime> {
+        if self.datetime.is_some() {
+            return self.datetime;
+        }
         for pat in [
             "cdda-experimental-%Y-%m-%d-%H%M",
             "cdda-experimental-%Y-%m-%d-%H-%M",
@@ -24,7 +36,7 @@ impl GitTag {
                 return Some(x);
             }
         }
-        return None;
+        None
     }
     pub fn tag_datetime(&self) -> chrono::NaiveDateTime {
         self.try_tag_datetime()
@@ -38,15 +50,20 @@ pub struct ReleaseHub {
     tags_info: RefCell<HashMap<GitTag, GithubRelease>>,
     // releases: Vec<GithubRelease>,
     blacklist: ReleaseBlacklist,
+    local: LocalBuilds,
+    pub sources: Sources,
     client: ApiClient,
 }
 impl ReleaseHub {
-    pub fn find_tag<'a>(&'a self, tag: &str) -> &'a GitTag {
-        self.tags_list.iter().find(|r| r.name == tag).unwrap()
+    pub fn find_tag<'a>(&'a self, tag: &str) -> anyhow::Result<&'a GitTag> {
+        self.tags_list
+            .iter()
+            .find(|r| r.name == tag)
+            .with_context(|| format!("{:?} is not among the known releases", tag))
     }
-    pub fn load() -> anyhow::Result<Self> {
+    pub fn load(sources: &[SourceConfig]) -> anyhow::Result<Self> {
         // let git_path = git_repo_path.into();
-        let blacklist = ReleaseBlacklist::load().with_context(|| format!("loading blacklist"))?;
+        let blacklist = ReleaseBlacklist::load().context("loading blacklist")?;
 
         // let inner = trim_releases(get_all_releases(false)?);
         let mut out = Self {
@@ -54,9 +71,20 @@ impl ReleaseHub {
             tags_info: Default::default(),
             // releases: inner,
             blacklist,
-            client: ApiClient::new(),
+            local: LocalBuilds::load().context("loading local builds")?,
+            sources: Sources(sources.iter().map(|s| s.build()).collect()),
+            // compares go to the configured API too
+            client: ApiClient::with_endpoint(
+                sources
+                    .iter()
+                    .find_map(|s| match s {
+                        SourceConfig::Github { api_url, .. } => Some(api_url.as_str()),
+                        _ => None,
+                    })
+                    .unwrap_or(GITHUB_API_ENDPOINT),
+            ),
         };
-        out.fetch_more_releases().with_context(|| format!("fetching releases"))?;
+        out.fetch_more_releases().context("fetching releases")?;
         Ok(out)
     }
 
@@ -66,14 +94,36 @@ impl ReleaseHub {
             .release_tags
             .iter()
             .collect::<std::collections::HashSet<_>>();
-        let tags_list = git_grab_tags_list();
-        let tags_list = tags_list
+        let mut tags_list = tags_list(self.sources.tags()?)
             .into_iter()
+            .chain(self.local.builds.iter().map(|b| b.tag()))
             .filter(|x| !bad_set.contains(&x.name))
             .collect::<Vec<_>>();
+        tags_list.sort_by_key(|t| std::cmp::Reverse((t.tag_datetime(), t.name.clone())));
+        for build in &self.local.builds {
+            self.tags_info
+                .borrow_mut()
+                .insert(build.tag(), build.release());
+        }
         self.tags_list = tags_list;
         Ok(())
     }
+    /// Registers a local build, and puts it on the timeline
+    pub fn add_local(
+        &mut self,
+        path: &std::path::Path,
+        datetime: chrono::NaiveDateTime,
+        sha: Option<String>,
+    ) -> anyhow::Result<GitTag> {
+        let build = self.local.add(path, datetime, sha)?;
+        self.tags_info
+            .borrow_mut()
+            .insert(build.tag(), build.release());
+        self.tags_list.push(build.tag());
+        self.tags_list
+            .sort_by_key(|t| std::cmp::Reverse((t.tag_datetime(), t.name.clone())));
+        Ok(build.tag())
+    }
     pub fn mark_blacklist(&mut self, release: &GithubRelease) -> anyhow::Result<()> {
         self.blacklist.add(release)
     }
@@ -87,38 +137,54 @@ impl ReleaseHub {
     //         }
     //     }
     // }
-    pub fn get_release(&self, tag: &GitTag) -> GithubRelease {
-        if !self.tags_info.borrow().contains_key(&tag) {
-            self.tags_info.borrow_mut().insert(
-                (*tag).clone(),
-                self.client.get_release_info(&tag.name).unwrap(),
-            );
+    /// The commits between two refs of `release`'s repo: asked of the source it's from,
+    /// and of upstream's API for the rest (local builds, mirrored releases, or a source that can't tell)
+    pub fn compare(
+        &self,
+        release: &GithubRelease,
+        base: &str,
+        head: &str,
+    ) -> anyhow::Result<GithubCompare> {
+        if let Some(source) = self.sources.of_release(release) {
+            if let Some(compare) = source.compare(base, head)? {
+                return Ok(compare);
+            }
         }
-        self.tags_info.borrow().get(tag).unwrap().clone()
+        self.client.get_compare(base, head)
     }
-}
-
-fn git_grab_tags_list() -> Vec<GitTag> {
-    let out = std::process::Command::new("git")
-        .args(["ls-remote", "--tags", "--refs", "--quiet"])
-        .arg("https://github.com/CleverRaven/Cataclysm-DDA.git")
-        .arg("cdda-experimental-*-*")
-        .output()
-        .unwrap();
-    let mut tags = vec![];
-    for line in String::from_utf8(out.stdout).unwrap().lines() {
-        let (_hash, tag) = line.split_once("\t").unwrap();
-        let tag = tag.trim();
-        let actual_tag = GitTag {
-            name: tag.split("/").last().unwrap().to_string(),
-        };
-        if actual_tag.try_tag_datetime().is_none() {
-            continue;
+    /// Link to the commits between two refs of `release`'s repo
+    pub fn compare_url(&self, release: &GithubRelease, base: &str, head: &str) -> String {
+        match self.sources.of_release(release).and_then(|s| s.repo_url()) {
+            Some(repo) => format!("{}/compare/{}...{}", repo, base, head),
+            None => compare_url(base, head),
         }
-
-        tags.push(actual_tag);
     }
-    tags.sort_by_key(|t| std::cmp::Reverse(t.name.to_owned()));
+    pub fn pull_url(&self, release: &GithubRelease, number: u32) -> String {
+        self.sources
+            .of_release(release)
+            .and_then(|s| s.pull_url(number))
+            .unwrap_or_else(|| pull_url(number))
+    }
+    pub fn get_release(&self, tag: &GitTag) -> anyhow::Result<GithubRelease> {
+        if !self.tags_info.borrow().contains_key(tag) {
+            let release = self
+                .sources
+                .release(&tag.name)
+                .with_context(|| format!("fetching release info of {:?}", tag.name))?;
+            self.tags_info.borrow_mut().insert((*tag).clone(), release);
+        }
+        Ok(self.tags_info.borrow().get(tag).unwrap().clone())
+    }
+}
+
+/// The tags that can be placed on the timeline, newest first
+fn tags_list(tags: Vec<GitTag>) -> Vec<GitTag> {
+    let mut tags = tags
+        .into_iter()
+        .filter(|t| t.try_tag_datetime().is_some())
+        .collect::<Vec<_>>();
+    tags.sort_by_key(|t| std::cmp::Reverse((t.tag_datetime(), t.name.clone())));
+    tags.dedup();
     println!(
         "Got {} releases, latest one being {:?}",
         tags.len(),
@@ -135,8 +201,19 @@ pub struct GithubRelease {
     //pub url: String,
     pub html_url: String,
     pub target_commitish: String,
+    /// registered with `add-local` rather than published on GitHub
+    #[serde(default)]
+    pub local: bool,
+}
+impl GithubRelease {
+    /// What to compare on GitHub: the tag, or the commit a local build was registered with
+    pub fn git_ref(&self) -> &str {
+        match self.local && !self.target_commitish.is_empty() {
+            true => &self.target_commitish,
+            false => &self.tag_name,
+        }
+    }
 }
-impl GithubRelease {}
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct ReleaseAsset {
@@ -145,186 +222,59 @@ pub struct ReleaseAsset {
 }
 
 #[derive(serde::Deserialize, Debug)]
-struct GithubCommitParent {
-    sha: String,
+pub struct GithubCompare {
+    pub total_commits: i64,
+    pub commits: Vec<GithubCompareCommit>,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct GithubCompareCommit {
+    pub commit: GithubCommitDetails,
 }
 #[derive(serde::Deserialize, Debug)]
-struct GithubCommit {
-    #[allow(dead_code)]
-    sha: String,
-    parents: Vec<GithubCommitParent>,
+pub struct GithubCommitDetails {
+    pub message: String,
+}
+
+pub fn compare_url(base: &str, head: &str) -> String {
+    format!(
+        "https://github.com/{}/compare/{}...{}",
+        CDDA_REPO, base, head
+    )
+}
+pub fn pull_url(number: u32) -> String {
+    format!("https://github.com/{}/pull/{}", CDDA_REPO, number)
 }
 
-struct ApiClient {
+pub struct ApiClient {
     agent: ureq::Agent,
+    endpoint: String,
 }
 impl ApiClient {
-    fn new() -> Self {
+    pub fn with_endpoint(endpoint: &str) -> Self {
         Self {
             agent: ureq::builder().user_agent("moxian-bisector-thingy").build(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
         }
     }
-    fn get_release_info(&self, release_tag: &str) -> anyhow::Result<GithubRelease> {
+    pub fn get_release_info(&self, release_tag: &str) -> anyhow::Result<GithubRelease> {
         let url = format!(
             "{}/repos/{}/releases/tags/{}",
-            GITHUB_API_ENDPOINT, CDDA_REPO, release_tag
+            self.endpoint, CDDA_REPO, release_tag
         );
         let thing = self.agent.get(&url).call()?.into_string()?;
         let release: GithubRelease = serde_json::from_str(&thing)?;
         Ok(release)
     }
-    fn get_release_list(&self, page: i32) -> anyhow::Result<Vec<GithubRelease>> {
-        let url = format!("{}/repos/{}/releases", GITHUB_API_ENDPOINT, CDDA_REPO);
-        let thing = self
-            .agent
-            .get(&url)
-            .query("per_page", "30")
-            .query("page", &page.to_string())
-            .call()?
-            .into_string()?;
-        let releases: Vec<GithubRelease> = serde_json::from_str(&thing)?;
-        return Ok(releases);
+    pub fn download(&self, url: &str) -> anyhow::Result<crate::release_source::AssetStream> {
+        Ok(Box::new(self.agent.get(url).call()?.into_reader()))
     }
-    fn get_parent_hash(&self, target: &str) -> anyhow::Result<String> {
+    pub fn get_compare(&self, base: &str, head: &str) -> anyhow::Result<GithubCompare> {
         let url = format!(
-            "{}/repos/{}/commits/{}",
-            GITHUB_API_ENDPOINT, CDDA_REPO, target
+            "{}/repos/{}/compare/{}...{}",
+            self.endpoint, CDDA_REPO, base, head
         );
-        let thing = self
-            .agent
-            .get(&url)
-            .query("per_page", "1")
-            .call()?
-            .into_string()?;
-
-        let response: GithubCommit = serde_json::from_str(&thing)?;
-        anyhow::ensure!(response.parents.len() == 1, "{:?}", response);
-        Ok(response.parents[0].sha.clone())
-    }
-}
-
-fn get_release_page(page: i32) -> anyhow::Result<Vec<GithubRelease>> {
-    log::info!("Fetching releases page {}", page);
-
-    let agent = ApiClient::new();
-    let releases = agent.get_release_list(page)?;
-    anyhow::ensure!(releases.len() > 0);
-    Ok(releases)
-}
-
-fn get_all_releases(network: bool) -> anyhow::Result<Vec<GithubRelease>> {
-    let latest_known_id: i64; // hardcoded because yay
-    let releases_cache_file = std::path::Path::new("cache/releases.json");
-    let mut all_releases: Vec<GithubRelease>;
-    if releases_cache_file.exists() {
-        all_releases = serde_json::from_str(&std::fs::read_to_string(releases_cache_file)?)?;
-        latest_known_id = all_releases[0].id;
-    } else {
-        all_releases = vec![];
-        latest_known_id = 168620093;
-    }
-
-    if network {
-        'pagination: for cur_page in 1.. {
-            let page_releases = get_release_page(cur_page)?;
-
-            for new_release in page_releases {
-                if new_release.id == latest_known_id {
-                    break 'pagination;
-                }
-                if all_releases.iter().any(|r| r.id == new_release.id) {
-                    continue;
-                }
-                all_releases.push(new_release)
-            }
-        }
-
-        std::fs::create_dir_all(releases_cache_file.parent().unwrap())?;
-        std::fs::File::create(releases_cache_file)?
-            .write_all(serde_json::to_string_pretty(&all_releases)?.as_bytes())?;
-    }
-    println!("got {} releases", all_releases.len());
-    Ok(all_releases)
-}
-
-fn fetch_more_releases() -> anyhow::Result<()> {
-    let extra_pages = 5;
-    let releases_cache_file = std::path::Path::new("cache/releases.json");
-    let mut all_releases: Vec<GithubRelease>;
-    if releases_cache_file.exists() {
-        all_releases = serde_json::from_str(&std::fs::read_to_string(releases_cache_file)?)?;
-    } else {
-        all_releases = vec![];
-    }
-
-    // fetch front
-    let mut caught_up = false;
-    let mut new_releases = vec![];
-    for cur_page in 1.. {
-        let page_of_releases = get_release_page(cur_page)?;
-
-        for new_release in page_of_releases {
-            if all_releases.iter().any(|ar| ar.id == new_release.id) {
-                caught_up = true;
-                break;
-            } else {
-                new_releases.push(new_release);
-            }
-        }
-        if all_releases.is_empty() {
-            caught_up = true;
-        }
-
-        if caught_up {
-            break;
-        }
-    }
-    let had_any_new = !new_releases.is_empty();
-    all_releases = new_releases
-        .into_iter()
-        .chain(all_releases)
-        .collect::<Vec<_>>();
-
-    if !had_any_new {
-        // now find the back
-        let page_size = 30;
-        let approx_start_page = all_releases.len() as i32 / page_size;
-        for cur_page in approx_start_page..approx_start_page + extra_pages {
-            let page_of_releases = get_release_page(cur_page)?;
-            let new_releases = page_of_releases
-                .into_iter()
-                .filter(|pr| !all_releases.iter().any(|ar| ar.id == pr.id))
-                .collect::<Vec<_>>();
-            all_releases.extend(new_releases);
-        }
+        let thing = self.agent.get(&url).call()?.into_string()?;
+        let compare: GithubCompare = serde_json::from_str(&thing)?;
+        Ok(compare)
     }
-
-    all_releases.sort_by_key(|r| -r.id);
-
-    std::fs::create_dir_all(releases_cache_file.parent().unwrap())?;
-    std::fs::File::create(releases_cache_file)?
-        .write_all(serde_json::to_string_pretty(&all_releases)?.as_bytes())?;
-
-    println!("got {} releases", all_releases.len());
-    Ok(())
-}
-
-fn trim_releases(releases: Vec<GithubRelease>) -> Vec<GithubRelease> {
-    let out = releases
-        .into_iter()
-        .filter(|r| r.tag_name.starts_with("cdda-experimental"))
-        .filter(|r| {
-            r.assets.iter().any(|a| {
-                a.name.starts_with("cdda-windows-tiles")
-                    || a.name.starts_with("cdda-windows-with-graphics")
-            })
-        })
-        .collect::<Vec<_>>();
-    out
-}
-
-#[allow(dead_code)]
-pub fn get_parent_commit(commit: &str) -> anyhow::Result<String> {
-    let agent = ApiClient::new();
-    agent.get_parent_hash(commit)
 }
@@ -0,0 +1,515 @@
+//! Where tag lists, release metadata and archives come from. `ReleaseHub` asks its sources in priority order,
+//! and takes the first answer - except for tags, which are gathered until a source that lists all of them.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::github_web::{GithubWeb, GITHUB_WEB};
+use crate::release_hub::{
+    ApiClient, GitTag, GithubCompare, GithubRelease, ReleaseAsset, GITHUB_API_ENDPOINT,
+};
+
+pub const CDDA_GIT_URL: &str = "https://github.com/CleverRaven/Cataclysm-DDA.git";
+
+pub type AssetStream = Box<dyn Read + Send>;
+
+/// Each method answers `None` for what the source doesn't have (or can't do at all)
+pub trait ReleaseSource {
+    /// for logs
+    fn describe(&self) -> String;
+    /// release tags in any order, with `datetime` set where the source knows better than the name
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>>;
+    /// whether `tags` is the whole timeline, rather than the latest few or whatever happens to be at hand
+    fn lists_all_tags(&self) -> bool {
+        false
+    }
+    fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>>;
+    fn open_asset(&self, asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>>;
+    /// where in `cache/release_info/` releases from here are kept, if they're worth keeping at all.
+    /// Upstream's go in the top level, which is what `serve` hands out
+    fn release_cache_dir(&self) -> Option<PathBuf> {
+        Some(crate::mirror::release_cache_dir())
+    }
+    /// the web page of the repo the releases are from, which their `html_url`s are under
+    fn repo_url(&self) -> Option<String> {
+        None
+    }
+    fn pull_url(&self, _number: u32) -> Option<String> {
+        None
+    }
+    /// the commits between two refs of that repo, for sources that can tell
+    fn compare(&self, _base: &str, _head: &str) -> anyhow::Result<Option<GithubCompare>> {
+        Ok(None)
+    }
+}
+
+/// One entry of `release_sources` in the config
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceConfig {
+    /// release metadata and downloads from the GitHub REST API, or its web pages when rate limited
+    Github {
+        #[serde(default = "default_api_url")]
+        api_url: String,
+        /// where the release pages are
+        #[serde(default = "default_web_url")]
+        web_url: String,
+    },
+    /// release metadata from the web pages only, and tags from `releases.atom` (just the latest few)
+    GithubWeb {
+        #[serde(default = "default_web_url")]
+        url: String,
+    },
+    /// tags from `git ls-remote`
+    Git {
+        #[serde(default = "default_git_url")]
+        url: String,
+    },
+    /// a folder of archives, e.g. another `distr_dir` or a network share
+    LocalDir { path: PathBuf },
+    /// another instance's `serve`
+    Mirror { url: String },
+    /// releases of a fork on a Gitea or Forgejo instance
+    Forgejo {
+        url: String,
+        /// `owner/name`
+        repo: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+}
+
+fn default_git_url() -> String {
+    CDDA_GIT_URL.into()
+}
+fn default_api_url() -> String {
+    GITHUB_API_ENDPOINT.into()
+}
+fn default_web_url() -> String {
+    GITHUB_WEB.into()
+}
+
+impl SourceConfig {
+    /// What's used when `release_sources` isn't set: the `mirrors`, then git and GitHub, then GitHub's web pages
+    pub fn defaults(mirrors: &[String]) -> Vec<SourceConfig> {
+        let mirrors = mirrors
+            .iter()
+            .map(|url| SourceConfig::Mirror { url: url.clone() });
+        mirrors
+            .chain([
+                SourceConfig::Git {
+                    url: default_git_url(),
+                },
+                SourceConfig::Github {
+                    api_url: default_api_url(),
+                    web_url: default_web_url(),
+                },
+                SourceConfig::GithubWeb {
+                    url: default_web_url(),
+                },
+            ])
+            .collect()
+    }
+
+    pub fn build(&self) -> Box<dyn ReleaseSource> {
+        match self {
+            SourceConfig::Github { api_url, web_url } => Box::new(GithubApi::new(api_url, web_url)),
+            SourceConfig::GithubWeb { url } => Box::new(GithubWeb::new(url)),
+            SourceConfig::Git { url } => Box::new(GitRemote { url: url.clone() }),
+            SourceConfig::LocalDir { path } => Box::new(LocalDir { dir: path.clone() }),
+            SourceConfig::Mirror { url } => Box::new(crate::mirror::MirrorSource::new(url)),
+            SourceConfig::Forgejo { url, repo, token } => {
+                Box::new(crate::forgejo::Forgejo::new(url, repo, token.clone()))
+            }
+        }
+    }
+}
+
+pub struct GithubApi {
+    client: ApiClient,
+    /// where to turn when the API says no
+    web: GithubWeb,
+}
+
+impl GithubApi {
+    pub fn new(api_endpoint: &str, web_base: &str) -> Self {
+        Self {
+            client: ApiClient::with_endpoint(api_endpoint),
+            web: GithubWeb::new(web_base),
+        }
+    }
+}
+
+impl ReleaseSource for GithubApi {
+    fn describe(&self) -> String {
+        "GitHub API".into()
+    }
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+        // `git ls-remote` does this without eating into the API quota
+        Ok(None)
+    }
+    fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+        match self.client.get_release_info(tag) {
+            Ok(release) => Ok(Some(release)),
+            Err(e) if rate_limited(&e) => {
+                log::warn!("GitHub API rate limited, reading the release page instead");
+                self.web.release(tag)
+            }
+            Err(e) => Err(e),
+        }
+    }
+    fn open_asset(&self, asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+        if !asset.browser_download_url.starts_with("http") {
+            return Ok(None);
+        }
+        Ok(Some(self.client.download(&asset.browser_download_url)?))
+    }
+    fn repo_url(&self) -> Option<String> {
+        self.web.repo_url()
+    }
+    fn pull_url(&self, number: u32) -> Option<String> {
+        self.web.pull_url(number)
+    }
+    fn compare(&self, base: &str, head: &str) -> anyhow::Result<Option<GithubCompare>> {
+        Ok(Some(self.client.get_compare(base, head)?))
+    }
+}
+
+fn rate_limited(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<ureq::Error>(),
+        Some(ureq::Error::Status(403 | 429, _))
+    )
+}
+
+pub struct GitRemote {
+    url: String,
+}
+
+impl ReleaseSource for GitRemote {
+    fn describe(&self) -> String {
+        format!("git remote {}", self.url)
+    }
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+        let out = std::process::Command::new("git")
+            .args(["ls-remote", "--tags", "--refs", "--quiet"])
+            .arg(&self.url)
+            .arg("cdda-experimental-*-*")
+            .output()?;
+        anyhow::ensure!(
+            out.status.success(),
+            "git ls-remote: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+        let mut tags = vec![];
+        for line in String::from_utf8(out.stdout)?.lines() {
+            let (_hash, tag) = line
+                .split_once('\t')
+                .with_context(|| anyhow::format_err!("unexpected ls-remote line {:?}", line))?;
+            tags.push(GitTag::named(tag.trim().rsplit('/').next().unwrap()));
+        }
+        Ok(Some(tags))
+    }
+    fn lists_all_tags(&self) -> bool {
+        true
+    }
+    fn release(&self, _tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+        Ok(None)
+    }
+    fn open_asset(&self, _asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+        Ok(None)
+    }
+}
+
+/// Archives named like the release assets (`cdda-windows-tiles-x64-2024-01-01-0100.zip`), and optionally
+/// `<tag>.json` release metadata next to them. Without the metadata, releases are made up from the archive names.
+pub struct LocalDir {
+    dir: PathBuf,
+}
+
+/// `cdda-experimental-2024-01-01-0100` for an asset name ending in `2024-01-01-0100` and an extension
+fn tag_of_archive(name: &str) -> Option<String> {
+    let re = regex::Regex::new(r"(\d{4}-\d{2}-\d{2}-\d{4})\.[a-z.0-9]+$").unwrap();
+    Some(format!("cdda-experimental-{}", &re.captures(name)?[1]))
+}
+
+impl LocalDir {
+    fn files(&self) -> anyhow::Result<Vec<String>> {
+        let mut out = vec![];
+        if !self.dir.exists() {
+            return Ok(out);
+        }
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                out.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        out.sort();
+        Ok(out)
+    }
+}
+
+impl ReleaseSource for LocalDir {
+    fn describe(&self) -> String {
+        format!("local dir {:?}", self.dir)
+    }
+    fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+        let mut tags = self
+            .files()?
+            .iter()
+            .filter_map(|f| match f.strip_suffix(".json") {
+                Some(tag) => Some(tag.to_string()),
+                None => tag_of_archive(f),
+            })
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
+        Ok(Some(tags.into_iter().map(GitTag::named).collect()))
+    }
+    fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+        let metadata = self.dir.join(format!("{}.json", tag));
+        if metadata.exists() {
+            return Ok(Some(serde_json::from_str(&std::fs::read_to_string(
+                metadata,
+            )?)?));
+        }
+        let assets = self
+            .files()?
+            .into_iter()
+            .filter(|f| tag_of_archive(f).as_deref() == Some(tag))
+            .map(|name| ReleaseAsset {
+                browser_download_url: format!("file://{}", self.dir.join(&name).to_string_lossy()),
+                name,
+            })
+            .collect::<Vec<_>>();
+        if assets.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(GithubRelease {
+            id: 0,
+            published_at: String::new(),
+            tag_name: tag.to_string(),
+            assets,
+            html_url: String::new(),
+            target_commitish: String::new(),
+            local: false,
+        }))
+    }
+    fn open_asset(&self, asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+        let file = self.dir.join(&asset.name);
+        if !file.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(std::fs::File::open(file)?)))
+    }
+    fn release_cache_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Sources by priority
+pub struct Sources(pub Vec<Box<dyn ReleaseSource>>);
+
+impl Sources {
+    /// The tags of all sources up to the first one that lists all of them, each tag once.
+    /// A tag whose date some source knows better than the name keeps that date.
+    pub fn tags(&self) -> anyhow::Result<Vec<GitTag>> {
+        let mut out = std::collections::BTreeMap::<String, GitTag>::new();
+        let mut answered = false;
+        for source in &self.0 {
+            match source.tags() {
+                Ok(Some(tags)) => {
+                    log::info!("Got {} tags from {}", tags.len(), source.describe());
+                    answered = true;
+                    for tag in tags {
+                        let known = out.entry(tag.name.clone()).or_insert(tag.clone());
+                        known.datetime = known.datetime.or(tag.datetime);
+                    }
+                    if source.lists_all_tags() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("listing tags from {}: {:?}", source.describe(), e),
+            }
+        }
+        anyhow::ensure!(answered, "none of the release sources could list the tags");
+        Ok(out.into_values().collect())
+    }
+
+    /// The source whose repo `release` is from, if any is
+    pub fn of_release(&self, release: &GithubRelease) -> Option<&dyn ReleaseSource> {
+        self.0
+            .iter()
+            .find(|s| {
+                s.repo_url()
+                    .is_some_and(|url| release.html_url.starts_with(&format!("{}/", url)))
+            })
+            .map(|s| s.as_ref())
+    }
+
+    /// The release from the first source that has it, from the on-disk cache where it was fetched before
+    pub fn release(&self, tag: &str) -> anyhow::Result<GithubRelease> {
+        for source in &self.0 {
+            let cache_dir = source.release_cache_dir();
+            if let Some(release) = cache_dir
+                .as_ref()
+                .and_then(|dir| crate::mirror::cached_release(dir, tag))
+            {
+                return Ok(release);
+            }
+            match source.release(tag) {
+                Ok(Some(release)) => {
+                    // scraped pages that didn't give the date are worth another try later
+                    if let (Some(dir), false) = (cache_dir, release.published_at.is_empty()) {
+                        crate::mirror::cache_release(&dir, &release)?;
+                    }
+                    return Ok(release);
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("{} from {}: {:?}", tag, source.describe(), e),
+            }
+        }
+        anyhow::bail!("none of the release sources have {:?}", tag)
+    }
+
+    /// Downloads the asset from the first source that has it. Sources whose download breaks off are skipped.
+    pub fn download(&self, asset: &ReleaseAsset, dest: &Path) -> anyhow::Result<()> {
+        let part = dest.with_file_name(format!("{}.part", asset.name));
+        for source in &self.0 {
+            let mut stream = match source.open_asset(asset) {
+                Ok(Some(stream)) => stream,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("{} from {}: {:?}", asset.name, source.describe(), e);
+                    continue;
+                }
+            };
+            log::info!("Downloading {} from {}..", asset.name, source.describe());
+            let copied = std::fs::File::create(&part)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut file| {
+                    std::io::copy(&mut stream, &mut file)?;
+                    Ok(file.flush()?)
+                });
+            match copied {
+                Ok(()) => {
+                    std::fs::rename(&part, dest)?;
+                    log::info!("..done");
+                    return Ok(());
+                }
+                Err(e) => {
+                    log::warn!("..failed: {:?}", e);
+                    std::fs::remove_file(&part).ok();
+                }
+            }
+        }
+        anyhow::bail!("none of the release sources have {:?}", asset.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Knows one release, and fails everything else
+    struct Fixture {
+        tag: &'static str,
+        body: &'static [u8],
+        lists_all_tags: bool,
+    }
+
+    impl ReleaseSource for Fixture {
+        fn describe(&self) -> String {
+            format!("fixture {}", self.tag)
+        }
+        fn tags(&self) -> anyhow::Result<Option<Vec<GitTag>>> {
+            Ok(Some(vec![GitTag::named(self.tag)]))
+        }
+        fn lists_all_tags(&self) -> bool {
+            self.lists_all_tags
+        }
+        // not in the tests' current dir
+        fn release_cache_dir(&self) -> Option<PathBuf> {
+            None
+        }
+        fn release(&self, tag: &str) -> anyhow::Result<Option<GithubRelease>> {
+            anyhow::ensure!(tag == self.tag, "unknown tag");
+            Ok(Some(GithubRelease {
+                id: 1,
+                published_at: String::new(),
+                tag_name: tag.to_string(),
+                assets: vec![],
+                html_url: String::new(),
+                target_commitish: String::new(),
+                local: false,
+            }))
+        }
+        fn open_asset(&self, _asset: &ReleaseAsset) -> anyhow::Result<Option<AssetStream>> {
+            Ok(Some(Box::new(self.body)))
+        }
+    }
+
+    #[test]
+    fn local_dir_and_priorities() {
+        let dir = std::env::temp_dir().join(format!("release_source_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("cdda-windows-tiles-x64-2024-01-01-0100.zip"),
+            b"local",
+        )
+        .unwrap();
+        let sources = Sources(vec![
+            SourceConfig::LocalDir { path: dir.clone() }.build(),
+            Box::new(Fixture {
+                tag: "cdda-experimental-2024-02-02-0200",
+                body: b"remote",
+                lists_all_tags: true,
+            }),
+            Box::new(Fixture {
+                tag: "cdda-experimental-2024-03-03-0300",
+                body: b"never asked",
+                lists_all_tags: true,
+            }),
+        ]);
+
+        // tags are gathered up to the first source that has them all
+        assert_eq!(
+            sources.tags().unwrap(),
+            vec![
+                GitTag::named("cdda-experimental-2024-01-01-0100"),
+                GitTag::named("cdda-experimental-2024-02-02-0200"),
+            ]
+        );
+        // releases fall through to whoever has them
+        let local = sources
+            .release("cdda-experimental-2024-01-01-0100")
+            .unwrap();
+        assert!(sources.0[0].release_cache_dir().is_none());
+        assert_eq!(
+            local.assets[0].name,
+            "cdda-windows-tiles-x64-2024-01-01-0100.zip"
+        );
+        let remote = sources
+            .release("cdda-experimental-2024-02-02-0200")
+            .unwrap();
+        assert_eq!(remote.id, 1);
+        assert!(sources
+            .release("cdda-experimental-2024-04-04-0400")
+            .is_err());
+
+        let out = dir.join("out.zip");
+        sources.download(&local.assets[0], &out).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"local");
+        let other = ReleaseAsset {
+            name: "cdda-windows-tiles-x64-2024-02-02-0200.zip".into(),
+            browser_download_url: String::new(),
+        };
+        sources.download(&other, &out).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"remote");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
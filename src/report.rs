@@ -0,0 +1,157 @@
+use std::fmt::Write;
+
+use crate::release_hub::GithubCompare;
+use crate::BisectState;
+
+/// (number, title) of every PR merged in the compared range
+fn candidate_prs(compare: &GithubCompare) -> Vec<(u32, String)> {
+    let merge_re = regex::Regex::new(r"^Merge pull request #(\d+)").unwrap();
+    let squash_re = regex::Regex::new(r"^(.*) \(#(\d+)\)$").unwrap();
+    let mut out = vec![];
+    for c in &compare.commits {
+        let mut lines = c.commit.message.lines();
+        let first = lines.next().unwrap_or("").trim();
+        if let Some(m) = merge_re.captures(first) {
+            let title = lines.map(|l| l.trim()).find(|l| !l.is_empty());
+            out.push((m[1].parse().unwrap(), title.unwrap_or(first).to_string()));
+        } else if let Some(m) = squash_re.captures(first) {
+            out.push((m[2].parse().unwrap(), m[1].to_string()));
+        }
+    }
+    out
+}
+
+fn asset_variant(asset_name: &str) -> String {
+    let re = regex::Regex::new(r"-\d{4}-\d{2}-\d{2}-\d{2}-?\d{2}.*$").unwrap();
+    re.replace(asset_name, "").to_string()
+}
+
+fn steps_left_after(state: &BisectState, n: usize) -> Option<i32> {
+    let (Some(good), Some(bad)) = state.track.bounds_after(n, &state.releases.tags_list) else {
+        return None;
+    };
+    let tags = &state.releases.tags_list;
+    let good = tags.iter().find(|t| t.name == good)?;
+    let bad = tags.iter().find(|t| t.name == bad)?;
+    Some(crate::bisecting::get_steps_left(&state.releases, good, bad))
+}
+
+pub fn render(state: &BisectState) -> anyhow::Result<String> {
+    let (Some(latest_good), Some(earliest_bad)) = state.track.bounds(&state.releases.tags_list)
+    else {
+        anyhow::bail!("need at least one good and one bad mark to make a report");
+    };
+    let good_rel = state
+        .releases
+        .get_release(state.releases.find_tag(&latest_good)?)?;
+    let bad_rel = state
+        .releases
+        .get_release(state.releases.find_tag(&earliest_bad)?)?;
+
+    let mut out = String::new();
+    writeln!(out, "### Regression range")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "- Latest good: [{}]({}) (`{}`)",
+        good_rel.tag_name, good_rel.html_url, good_rel.target_commitish
+    )?;
+    writeln!(
+        out,
+        "- Earliest bad: [{}]({}) (`{}`)",
+        bad_rel.tag_name, bad_rel.html_url, bad_rel.target_commitish
+    )?;
+    writeln!(
+        out,
+        "- Commit range: `( {} , {} ]`",
+        good_rel.target_commitish, bad_rel.target_commitish
+    )?;
+    writeln!(
+        out,
+        "- Compare: {}",
+        state
+            .releases
+            .compare_url(&bad_rel, good_rel.git_ref(), bad_rel.git_ref())
+    )?;
+    writeln!(out)?;
+
+    writeln!(out, "### Candidate PRs")?;
+    writeln!(out)?;
+    match state
+        .releases
+        .compare(&bad_rel, good_rel.git_ref(), bad_rel.git_ref())
+    {
+        Ok(compare) => {
+            let prs = candidate_prs(&compare);
+            if prs.is_empty() {
+                writeln!(out, "No PRs found in {} commits.", compare.total_commits)?;
+            }
+            for (number, title) in prs {
+                writeln!(
+                    out,
+                    "- [#{}]({}) {}",
+                    number,
+                    state.releases.pull_url(&bad_rel, number),
+                    title
+                )?;
+            }
+        }
+        Err(e) => {
+            log::warn!("couldn't fetch commit range: {:?}", e);
+            writeln!(
+                out,
+                "Couldn't fetch the commit list, see the compare link above."
+            )?;
+        }
+    }
+    writeln!(out)?;
+
+    writeln!(out, "### Bisection track")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "| # | Tag | Verdict | Asset | Marked at | Exit | Steps left | Note |"
+    )?;
+    writeln!(
+        out,
+        "|---|-----|---------|-------|-----------|------|------------|------|"
+    )?;
+    for (i, entry) in state.track.0.iter().enumerate() {
+        let steps_left = steps_left_after(state, i + 1)
+            .map(|s| s.to_string())
+            .unwrap_or("?".into());
+        writeln!(
+            out,
+            "| {} | {} | {:?} | {} | {} | {} | {} | {} |",
+            i + 1,
+            entry.tag,
+            entry.goodness,
+            entry.asset.as_deref().unwrap_or(""),
+            entry.marked_at.as_deref().unwrap_or(""),
+            entry.exit_status.as_deref().unwrap_or(""),
+            steps_left,
+            entry.note.as_deref().unwrap_or("").replace('|', "\\|"),
+        )?;
+    }
+    writeln!(out)?;
+
+    let mut variants = state
+        .track
+        .0
+        .iter()
+        .filter_map(|e| e.asset.as_deref())
+        .map(asset_variant)
+        .collect::<Vec<_>>();
+    variants.sort();
+    variants.dedup();
+    writeln!(out, "### Environment")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "- Host: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )?;
+    writeln!(out, "- Tested asset: {}", variants.join(", "))?;
+    Ok(out)
+}
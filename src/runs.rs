@@ -0,0 +1,83 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct RunRecord {
+    pub tag: String,
+    pub asset: String,
+    pub launched_at: String,
+    pub duration_secs: f64,
+    pub exit_status: String,
+}
+
+pub struct Run {
+    pub dir: PathBuf,
+    pub record: RunRecord,
+}
+
+fn runs_dir() -> PathBuf {
+    Path::new("cache/runs").into()
+}
+
+pub fn new_run_dir(
+    tag: &str,
+    launched_at: &chrono::DateTime<chrono::Local>,
+) -> anyhow::Result<PathBuf> {
+    let dir = runs_dir()
+        .join(tag)
+        .join(launched_at.format("%Y-%m-%d-%H%M%S").to_string());
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Removes the dir of a run that never got going
+pub fn discard(dir: &Path) {
+    if let Err(e) = std::fs::remove_dir_all(dir) {
+        log::warn!("couldn't remove {:?}: {:?}", dir, e);
+    }
+}
+
+/// Writes `run.json` and copies the logs the game left in the userdir into `logs/`, keeping their place
+/// in the userdir since there may be several `crash.log`s.
+pub fn finish(
+    dir: &Path,
+    record: RunRecord,
+    userdata_dir: &Path,
+    logs: &[PathBuf],
+) -> anyhow::Result<Run> {
+    for log in logs {
+        let relative = log
+            .strip_prefix(userdata_dir)
+            .unwrap_or(Path::new(log.file_name().unwrap()));
+        let target = dir.join("logs").join(relative);
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        std::fs::copy(log, target)?;
+    }
+    std::fs::File::create(dir.join("run.json"))?
+        .write_all(serde_json::to_string_pretty(&record)?.as_bytes())?;
+    Ok(Run {
+        dir: dir.into(),
+        record,
+    })
+}
+
+/// All recorded runs of `tag`, oldest first
+pub fn list(tag: &str) -> anyhow::Result<Vec<Run>> {
+    let tag_dir = runs_dir().join(tag);
+    if !tag_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut out = vec![];
+    for entry in std::fs::read_dir(tag_dir)? {
+        let dir = entry?.path();
+        let Ok(text) = std::fs::read_to_string(dir.join("run.json")) else {
+            continue;
+        };
+        out.push(Run {
+            dir,
+            record: serde_json::from_str(&text)?,
+        });
+    }
+    out.sort_by(|a, b| a.dir.cmp(&b.dir));
+    Ok(out)
+}
@@ -0,0 +1,198 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::fsutil::copy_dir_all;
+
+/// A world that gets restored from a snapshot before every launch
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PinnedSave {
+    pub world: String,
+    /// when the game last wrote the world, before it was pinned
+    #[serde(default)]
+    pub written_at: Option<chrono::NaiveDateTime>,
+}
+impl PinnedSave {
+    fn pin_file() -> PathBuf {
+        Path::new("cache/pinned_save.json").into()
+    }
+    fn snapshot_dir(&self) -> PathBuf {
+        Path::new("cache/pinned_save").join(&self.world)
+    }
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let file = Self::pin_file();
+        if !file.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&file).with_context(|| format!("reading {:?}", file))?;
+        Ok(Some(serde_json::from_str(&text)?))
+    }
+    /// Snapshots `world` from the userdir and remembers it as the pinned one.
+    pub fn pin(userdata_dir: &Path, world: &str) -> anyhow::Result<Self> {
+        let world_dir = userdata_dir.join("save").join(world);
+        anyhow::ensure!(world_dir.is_dir(), "no world at {:?}", world_dir);
+        let written_at = std::fs::metadata(world_dir.join("master.gsav"))
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).naive_utc());
+        let out = Self {
+            world: world.to_string(),
+            written_at,
+        };
+        let snapshot = out.snapshot_dir();
+        if snapshot.exists() {
+            std::fs::remove_dir_all(&snapshot)?;
+        }
+        copy_dir_all(&world_dir, &snapshot)?;
+        std::fs::File::create(Self::pin_file())?
+            .write_all(serde_json::to_string_pretty(&out)?.as_bytes())?;
+        Ok(out)
+    }
+    pub fn unpin() -> anyhow::Result<()> {
+        std::fs::remove_file(Self::pin_file()).ok();
+        std::fs::remove_dir_all("cache/pinned_save").ok();
+        Ok(())
+    }
+    /// Replaces the world in the userdir with the pristine snapshot.
+    pub fn restore(&self, userdata_dir: &Path) -> anyhow::Result<()> {
+        let world_dir = userdata_dir.join("save").join(&self.world);
+        if world_dir.exists() {
+            std::fs::remove_dir_all(&world_dir)?;
+        }
+        copy_dir_all(&self.snapshot_dir(), &world_dir)
+            .with_context(|| format!("restoring pinned save {:?}", self.world))
+    }
+    pub fn snapshot_version(&self) -> Option<u32> {
+        save_version(&self.snapshot_dir())
+    }
+    /// Save version of the world in the userdir, if the game saved it since `since`
+    pub fn written_version(
+        &self,
+        userdata_dir: &Path,
+        since: std::time::SystemTime,
+    ) -> Option<u32> {
+        let world_dir = userdata_dir.join("save").join(&self.world);
+        let modified = std::fs::metadata(world_dir.join("master.gsav"))
+            .ok()?
+            .modified()
+            .ok()?;
+        if modified < since {
+            return None;
+        }
+        save_version(&world_dir)
+    }
+    /// Keeps the world as the game left it, for comparing between versions later.
+    pub fn archive(&self, userdata_dir: &Path, dest: &Path) -> anyhow::Result<()> {
+        let world_dir = userdata_dir.join("save").join(&self.world);
+        if world_dir.exists() {
+            copy_dir_all(&world_dir, &dest.join(&self.world))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn list_worlds(userdata_dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(userdata_dir.join("save")) else {
+        return vec![];
+    };
+    let mut out = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    out.sort();
+    out
+}
+
+/// The savegame format version from the `# version N` header of the world's `master.gsav`
+pub fn save_version(world_dir: &Path) -> Option<u32> {
+    let text = std::fs::read_to_string(world_dir.join("master.gsav")).ok()?;
+    let first = text.lines().next()?;
+    first.strip_prefix("# version ")?.trim().parse().ok()
+}
+
+pub enum SaveCompat {
+    Compatible,
+    /// a build at least as new wrote an older format; carries the closest known build that can load it
+    Incompatible {
+        nearest: Option<String>,
+    },
+    /// the build predates the save, so whatever wrote it may be newer
+    PredatesSave {
+        written_at: chrono::NaiveDateTime,
+    },
+    Unknown,
+}
+
+/// Which savegame version each launched tag wrote, learned from the runs so far
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct SaveVersions {
+    written_by: std::collections::BTreeMap<String, u32>,
+}
+impl SaveVersions {
+    fn versions_file() -> PathBuf {
+        Path::new("cache/save_versions.json").into()
+    }
+    pub fn load() -> anyhow::Result<Self> {
+        let file = Self::versions_file();
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(file)?)?)
+    }
+    pub fn record(&mut self, tag: &str, version: u32) -> anyhow::Result<()> {
+        self.written_by.insert(tag.to_string(), version);
+        std::fs::File::create(Self::versions_file())?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+    /// What the runs so far say about `tag` loading a save of `version`, or failing that, how `tag`'s date
+    /// compares to when the save was written
+    pub fn check(
+        &self,
+        tags: &crate::ReleaseHub,
+        tag: &str,
+        version: u32,
+        written_at: Option<chrono::NaiveDateTime>,
+    ) -> SaveCompat {
+        let date = |t: &str| {
+            tags.tags_list
+                .iter()
+                .find(|x| x.name == t)
+                .map(|x| x.tag_datetime())
+        };
+        let Some(candidate_date) = date(tag) else {
+            return SaveCompat::Unknown;
+        };
+        let known = self
+            .written_by
+            .iter()
+            .filter_map(|(t, v)| Some((date(t)?, t, *v)))
+            .collect::<Vec<_>>();
+        if known
+            .iter()
+            .any(|(d, _, v)| *d <= candidate_date && *v >= version)
+        {
+            return SaveCompat::Compatible;
+        }
+        if known
+            .iter()
+            .any(|(d, _, v)| *d >= candidate_date && *v < version)
+        {
+            let nearest = known
+                .iter()
+                .filter(|(_, _, v)| *v >= version)
+                .min_by_key(|(d, _, _)| (*d - candidate_date).abs())
+                .map(|(_, t, _)| t.to_string());
+            return SaveCompat::Incompatible { nearest };
+        }
+        match written_at {
+            Some(written_at) if candidate_date < written_at => {
+                SaveCompat::PredatesSave { written_at }
+            }
+            Some(_) => SaveCompat::Compatible,
+            None => SaveCompat::Unknown,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! `--dump-stats` tables of item/vehicle/monster stats, cached per tag, for balance regressions.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::oracle::{opposite, Predicate};
+use crate::Goodness;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct StatsOracle {
+    /// what to dump, e.g. `ITEM`, `VEHICLE` or `MONSTER`
+    #[serde(rename = "type")]
+    pub stats_type: String,
+    /// matched against `key_column`
+    pub row: String,
+    /// the first column if not set
+    #[serde(default)]
+    pub key_column: Option<String>,
+    pub column: String,
+    #[serde(default)]
+    pub predicate: Predicate,
+    /// compare with this tag's value instead of checking `predicate`; any difference counts as matching
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default = "crate::oracle::default_when_matching")]
+    pub when_matching: Goodness,
+}
+
+/// A parsed dump: the header, and rows of cells
+pub struct StatsTable {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+fn stats_file(tag: &str, stats_type: &str) -> PathBuf {
+    Path::new("cache/stats")
+        .join(tag)
+        .join(format!("{}.tsv", stats_type))
+}
+
+/// Runs the build's `--dump-stats`, unless this tag's dump is cached already
+pub fn dump(
+    build_dir: &Path,
+    executable: Option<&str>,
+    tag: &str,
+    stats_type: &str,
+) -> anyhow::Result<StatsTable> {
+    let file = stats_file(tag, stats_type);
+    if !file.exists() {
+        log::info!("Dumping {} stats of {}", stats_type, tag);
+        let output = crate::oracle::headless_game(build_dir, executable)?
+            .args(["--dump-stats", stats_type, "TSV"])
+            .output()
+            .context("running --dump-stats")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "--dump-stats {} {}",
+            stats_type,
+            crate::crash::describe_exit(&output.status)
+        );
+        std::fs::create_dir_all(file.parent().unwrap())?;
+        std::fs::write(&file, &output.stdout)?;
+    }
+    load(tag, stats_type)
+}
+
+/// A previously dumped table
+pub fn load(tag: &str, stats_type: &str) -> anyhow::Result<StatsTable> {
+    let file = stats_file(tag, stats_type);
+    let text = std::fs::read_to_string(&file).with_context(|| {
+        anyhow::format_err!("no {} stats cached for {}, judge it first", stats_type, tag)
+    })?;
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let split = |l: &str| {
+        l.split('\t')
+            .map(|c| c.trim().to_string())
+            .collect::<Vec<_>>()
+    };
+    let header = split(lines.next().unwrap_or_default());
+    let rows = lines.map(split).collect();
+    Ok(StatsTable { header, rows })
+}
+
+/// Numbers as numbers, so they can be compared with `lt`/`gt`. Whole ones stay integers, like they'd be
+/// written in the config
+fn cell_value(cell: &str) -> serde_json::Value {
+    if let Ok(x) = cell.parse::<i64>() {
+        return serde_json::json!(x);
+    }
+    match cell.parse::<f64>() {
+        Ok(x) => serde_json::json!(x),
+        Err(_) => serde_json::Value::String(cell.to_string()),
+    }
+}
+
+impl StatsTable {
+    fn column(&self, name: &str) -> anyhow::Result<usize> {
+        self.header
+            .iter()
+            .position(|h| h == name)
+            .with_context(|| anyhow::format_err!("no column {:?}, have {:?}", name, self.header))
+    }
+
+    /// Rows by their key column, the first one if not given
+    fn keyed(&self, key_column: Option<&str>) -> anyhow::Result<BTreeMap<&str, &[String]>> {
+        let key = match key_column {
+            Some(k) => self.column(k)?,
+            None => 0,
+        };
+        Ok(self
+            .rows
+            .iter()
+            .filter_map(|r| Some((r.get(key)?.as_str(), r.as_slice())))
+            .collect())
+    }
+
+    fn cell(
+        &self,
+        key_column: Option<&str>,
+        row: &str,
+        column: &str,
+    ) -> anyhow::Result<Option<&str>> {
+        let column = self.column(column)?;
+        Ok(self
+            .keyed(key_column)?
+            .get(row)
+            .and_then(|r| r.get(column))
+            .map(|c| c.as_str()))
+    }
+}
+
+impl StatsOracle {
+    pub fn judge(
+        &self,
+        build_dir: &Path,
+        executable: Option<&str>,
+        tag: &str,
+    ) -> anyhow::Result<Goodness> {
+        let table = dump(build_dir, executable, tag, &self.stats_type)?;
+        let key_column = self.key_column.as_deref();
+        let value = table.cell(key_column, &self.row, &self.column)?;
+        println!(
+            "{} {:?} {}: {:?}",
+            self.stats_type, self.row, self.column, value
+        );
+        let holds = match &self.reference {
+            Some(reference) => {
+                let expected = load(reference, &self.stats_type)?
+                    .cell(key_column, &self.row, &self.column)?
+                    .map(str::to_string);
+                value.map(str::to_string) != expected
+            }
+            None => self.predicate.holds(value.map(cell_value).as_ref()),
+        };
+        Ok(match holds {
+            true => self.when_matching,
+            false => opposite(self.when_matching),
+        })
+    }
+}
+
+/// Rows added, removed and changed (by cell) going from `old_tag`'s dump to `new_tag`'s
+pub fn diff(stats_type: &str, old_tag: &str, new_tag: &str) -> anyhow::Result<String> {
+    let old = load(old_tag, stats_type)?;
+    let new = load(new_tag, stats_type)?;
+    let old_rows = old.keyed(None)?;
+    let new_rows = new.keyed(None)?;
+    let mut out = String::new();
+    writeln!(out, "{} stats, {} -> {}", stats_type, old_tag, new_tag)?;
+    if old.header != new.header {
+        writeln!(out, "  columns: {:?} -> {:?}", old.header, new.header)?;
+    }
+    for (key, row) in &old_rows {
+        let Some(new_row) = new_rows.get(key) else {
+            writeln!(out, "- {}", key)?;
+            continue;
+        };
+        let mut changes = vec![];
+        for (i, name) in old.header.iter().enumerate() {
+            let Ok(j) = new.column(name) else {
+                continue;
+            };
+            let (before, after) = (row.get(i), new_row.get(j));
+            if before != after {
+                changes.push(format!(
+                    "{}: {} -> {}",
+                    name,
+                    before.map(|s| s.as_str()).unwrap_or("(none)"),
+                    after.map(|s| s.as_str()).unwrap_or("(none)")
+                ));
+            }
+        }
+        if !changes.is_empty() {
+            writeln!(out, "~ {}", key)?;
+            for change in changes {
+                writeln!(out, "    {}", change)?;
+            }
+        }
+    }
+    for key in new_rows.keys().filter(|k| !old_rows.contains_key(*k)) {
+        writeln!(out, "+ {}", key)?;
+    }
+    Ok(out)
+}
+
+/// Tags that have a dump of `stats_type` cached
+pub fn cached_tags(stats_type: &str) -> anyhow::Result<Vec<String>> {
+    let dir = Path::new("cache/stats");
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut out = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let tag = entry?.file_name().to_string_lossy().to_string();
+        if stats_file(&tag, stats_type).exists() {
+            out.push(tag);
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::Predicate;
+    use serde_json::json;
+
+    #[test]
+    fn numeric_cells_match_config_numbers() {
+        let table = StatsTable {
+            header: vec!["id".into(), "weight".into(), "volume".into()],
+            rows: vec![vec!["rock".into(), "10".into(), "0.25".into()]],
+        };
+        let cell = |column| cell_value(table.cell(None, "rock", column).unwrap().unwrap());
+        let eq = |v| Predicate::Eq(v);
+        assert!(eq(json!(10)).holds(Some(&cell("weight"))));
+        assert!(eq(json!(10.0)).holds(Some(&cell("weight"))));
+        assert!(!Predicate::Ne(json!(10)).holds(Some(&cell("weight"))));
+        assert!(eq(json!(0.25)).holds(Some(&cell("volume"))));
+        assert!(Predicate::Gt(9.5).holds(Some(&cell("weight"))));
+        assert_eq!(cell_value("rock"), json!("rock"));
+    }
+}
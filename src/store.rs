@@ -0,0 +1,181 @@
+//! Content-addressed storage for unpacked builds: every distinct file is kept once in `cache/store/`,
+//! and builds are made of hardlinks into it. Adjacent builds share almost all their files.
+//!
+//! Since the links share their content, anything writing into a build's files in place changes that file in
+//! every build, and in the store. Files the game writes are in the userdir, and overlays are copied in, so
+//! this only happens when something edits a build by hand - which `verify` catches.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::Digest;
+
+/// Which object each file of a build is, by path relative to the build
+const MANIFEST: &str = ".bisector_store.json";
+
+fn store_dir() -> PathBuf {
+    Path::new("cache/store").into()
+}
+
+fn object_path(hash: &str) -> PathBuf {
+    store_dir().join(&hash[..2]).join(hash)
+}
+
+pub fn hash_file(path: &Path) -> anyhow::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hardlinks where the filesystem allows, copies otherwise
+fn link_or_copy(object: &Path, dest: &Path) -> anyhow::Result<()> {
+    if std::fs::hard_link(object, dest).is_err() {
+        std::fs::copy(object, dest)?;
+    }
+    Ok(())
+}
+
+pub fn read_manifest(build_dir: &Path) -> anyhow::Result<Option<BTreeMap<PathBuf, String>>> {
+    let file = build_dir.join(MANIFEST);
+    if !file.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&std::fs::read_to_string(file)?)?))
+}
+
+/// Moves the files of a freshly unpacked build into the store and links them back.
+pub fn import(build_dir: &Path) -> anyhow::Result<()> {
+    let mut manifest = BTreeMap::new();
+    let (mut linked, mut new) = (0, 0);
+    for rel in crate::fsutil::list_files(build_dir)? {
+        let file = build_dir.join(&rel);
+        let hash = hash_file(&file)?;
+        let object = object_path(&hash);
+        if object.exists() {
+            std::fs::remove_file(&file)?;
+            linked += 1;
+        } else {
+            std::fs::create_dir_all(object.parent().unwrap())?;
+            if std::fs::rename(&file, &object).is_err() {
+                std::fs::copy(&file, &object)?;
+                std::fs::remove_file(&file)?;
+            }
+            new += 1;
+        }
+        link_or_copy(&object, &file)?;
+        manifest.insert(rel, hash);
+    }
+    std::fs::File::create(build_dir.join(MANIFEST))?
+        .write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    log::info!(
+        "Stored {:?}: {} files already in the store, {} new",
+        build_dir,
+        linked,
+        new
+    );
+    Ok(())
+}
+
+/// Build dirs under `unpack_dir` that went through the store
+fn stored_builds(unpack_dir: &Path) -> anyhow::Result<Vec<(PathBuf, BTreeMap<PathBuf, String>)>> {
+    let mut out = vec![];
+    if !unpack_dir.exists() {
+        return Ok(out);
+    }
+    for entry in std::fs::read_dir(unpack_dir)? {
+        let dir = entry?.path();
+        if let Some(manifest) = read_manifest(&dir)? {
+            out.push((dir, manifest));
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// objects whose content doesn't match their hash any more
+    pub corrupt_objects: Vec<String>,
+    /// build dir, and what's wrong with it
+    pub modified_builds: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Re-hashes the whole store, and checks every stored build against its manifest.
+pub fn verify(unpack_dir: &Path) -> anyhow::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    for rel in crate::fsutil::list_files(&store_dir())? {
+        let name = rel.file_name().unwrap().to_string_lossy().to_string();
+        if hash_file(&store_dir().join(&rel))? != name {
+            report.corrupt_objects.push(name);
+        }
+    }
+    for (dir, manifest) in stored_builds(unpack_dir)? {
+        // overlays are copied in on activation, they aren't part of the build
+        let overlays = crate::overlays::installed(&dir)?;
+        let mut problems = vec![];
+        for (rel, hash) in &manifest {
+            let file = dir.join(rel);
+            if !file.exists() {
+                problems.push(format!("missing {}", rel.display()));
+            } else if report.corrupt_objects.contains(hash) || &hash_file(&file)? != hash {
+                problems.push(format!("modified {}", rel.display()));
+            }
+        }
+        for rel in crate::fsutil::list_files(&dir)? {
+            let ours = rel == Path::new(MANIFEST)
+                || rel == Path::new(crate::overlays::MANIFEST)
+                || overlays.iter().any(|o| rel.starts_with(o));
+            if !ours && !manifest.contains_key(&rel) {
+                problems.push(format!("added {}", rel.display()));
+            }
+        }
+        if !problems.is_empty() {
+            report.modified_builds.push((dir, problems));
+        }
+    }
+    Ok(report)
+}
+
+/// Removes corrupt objects and the affected builds, so they get unpacked afresh when next activated.
+pub fn repair(report: &VerifyReport) -> anyhow::Result<()> {
+    for hash in &report.corrupt_objects {
+        std::fs::remove_file(object_path(hash))?;
+    }
+    for (dir, _) in &report.modified_builds {
+        log::info!("Removing {:?}", dir);
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Deletes objects no stored build refers to any more. Returns the bytes freed.
+pub fn prune(unpack_dir: &Path) -> anyhow::Result<u64> {
+    let referenced = stored_builds(unpack_dir)?
+        .into_iter()
+        .flat_map(|(_, manifest)| manifest.into_values())
+        .collect::<BTreeSet<_>>();
+    let mut freed = 0;
+    for rel in crate::fsutil::list_files(&store_dir())? {
+        let name = rel.file_name().unwrap().to_string_lossy().to_string();
+        if !referenced.contains(&name) {
+            let path = store_dir().join(&rel);
+            freed += std::fs::metadata(&path)?.len();
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(freed)
+}
+
+/// Total size of the store, counting every object once
+pub fn size() -> anyhow::Result<u64> {
+    crate::cache::dir_size(&store_dir())
+}
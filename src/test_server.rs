@@ -0,0 +1,93 @@
+//! A throwaway HTTP server for tests. Serves fixed responses by path and honours `Range`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+pub struct TestServer {
+    pub url: String,
+    /// `"<method> <path> <range>"` of every request so far
+    pub requests: Arc<Mutex<Vec<String>>>,
+}
+
+type Routes = HashMap<String, (u16, Vec<u8>)>;
+
+/// `routes` maps a path (without the query) to a status and a body
+pub fn serve(routes: Routes) -> TestServer {
+    serve_with(|_| routes)
+}
+
+/// For responses that mention the server's own url
+pub fn serve_with(routes: impl FnOnce(&str) -> Routes) -> TestServer {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let routes = routes(&url);
+    let requests = Arc::new(Mutex::new(vec![]));
+    let log = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut range = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.trim().to_string());
+                    }
+                }
+            }
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or("").to_string();
+            let target = parts.next().unwrap_or("");
+            let path = target.split('?').next().unwrap().to_string();
+            log.lock().unwrap().push(format!(
+                "{} {} {}",
+                method,
+                path,
+                range.as_deref().unwrap_or("")
+            ));
+
+            let (status, body) = routes.get(&path).cloned().unwrap_or((404, vec![]));
+            let (status, body, content_range) = match range.as_deref().and_then(|r| {
+                let (start, end) = r.strip_prefix("bytes=")?.split_once('-')?;
+                let len = body.len();
+                let (start, end) = match (start.parse::<usize>(), end.parse::<usize>()) {
+                    (Ok(s), Ok(e)) => (s, e.min(len - 1)),
+                    (Ok(s), Err(_)) => (s, len - 1),
+                    (Err(_), Ok(n)) => (len - n.min(len), len - 1),
+                    _ => return None,
+                };
+                Some((start, end, len))
+            }) {
+                Some((start, end, len)) if status == 200 => (
+                    206,
+                    body[start..=end].to_vec(),
+                    Some(format!("bytes {}-{}/{}", start, end, len)),
+                ),
+                _ => (status, body, None),
+            };
+            let mut head = format!(
+                "HTTP/1.1 {} X\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n",
+                status,
+                body.len()
+            );
+            if let Some(cr) = content_range {
+                head += &format!("Content-Range: {}\r\n", cr);
+            }
+            head += "\r\n";
+            stream.write_all(head.as_bytes()).ok();
+            if method != "HEAD" {
+                stream.write_all(&body).ok();
+            }
+        }
+    });
+    TestServer { url, requests }
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+
+use crate::fsutil::copy_dir_all;
+
+/// userdir subfolders worth carrying over; `memorial` and `graveyard` are deliberately absent
+const IMPORTED_DIRS: &[&str] = &["config", "mods", "gfx", "sound", "font", "templates"];
+
+fn expand_home(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return Path::new(&home).join(rest);
+        }
+    }
+    path.into()
+}
+
+/// With the XDG layout on linux the config lives in `~/.config/cataclysm-dda`
+/// rather than next to the saves in `~/.local/share/cataclysm-dda`.
+fn find_config_dir(source: &Path) -> Option<PathBuf> {
+    let local = source.join("config");
+    if local.is_dir() {
+        return Some(local);
+    }
+    let home = source.parent()?.parent()?.parent()?;
+    let xdg = home.join(".config").join(source.file_name()?);
+    xdg.is_dir().then_some(xdg)
+}
+
+fn ask_worlds(worlds: &[String]) -> Vec<String> {
+    if worlds.is_empty() {
+        return vec![];
+    }
+    for (i, w) in worlds.iter().enumerate() {
+        println!("  {}) {}", i + 1, w);
+    }
+    println!("Worlds to import (numbers separated by spaces, `all`, or nothing to skip saves):");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).unwrap();
+    if answer.trim() == "all" {
+        return worlds.to_vec();
+    }
+    answer
+        .split_whitespace()
+        .filter_map(|n| n.parse::<usize>().ok())
+        .filter_map(|n| worlds.get(n.wrapping_sub(1)).cloned())
+        .collect()
+}
+
+/// Copies the children of `from` that the game doesn't ship itself.
+/// A windows install keeps its bundled tilesets in the same `gfx` folder as the user's.
+fn copy_user_content(from: &Path, to: &Path, shipped: Option<&Path>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if shipped.is_some_and(|s| s.join(entry.file_name()).exists()) {
+            log::debug!("skipping bundled {:?}", entry.path());
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            std::fs::create_dir_all(to)?;
+            std::fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// `shipped_build` is an unpacked build, used to tell bundled content apart from the user's
+pub fn import(source: &str, target: &Path, shipped_build: Option<&Path>) -> anyhow::Result<()> {
+    let source = expand_home(source);
+    anyhow::ensure!(source.is_dir(), "{:?} is not a directory", source);
+    std::fs::create_dir_all(target)?;
+
+    for dir in IMPORTED_DIRS {
+        let from = match *dir {
+            "config" => find_config_dir(&source),
+            _ => Some(source.join(dir)).filter(|d| d.is_dir()),
+        };
+        let Some(from) = from else {
+            continue;
+        };
+        println!(
+            "Copying {} -> {}",
+            from.to_string_lossy(),
+            target.join(dir).to_string_lossy()
+        );
+        let is_install = source.join("data").is_dir();
+        match (*dir, is_install) {
+            ("gfx", true) => {
+                let shipped = shipped_build.map(|b| b.join(dir));
+                copy_user_content(&from, &target.join(dir), shipped.as_deref())?
+            }
+            _ => copy_dir_all(&from, &target.join(dir))?,
+        }
+    }
+
+    let worlds = crate::saves::list_worlds(&source);
+    for world in ask_worlds(&worlds) {
+        println!("Copying world {:?}", world);
+        copy_dir_all(
+            &source.join("save").join(&world),
+            &target.join("save").join(&world),
+        )?;
+    }
+    Ok(())
+}